@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 // CLI Arguments
 #[derive(Parser, Debug)]
@@ -27,4 +28,17 @@ pub struct Args {
     /// Upstream host address
     #[arg(long, default_value = "127.0.0.1")]
     pub upstream_host: String,
+
+    /// JSON or TOML file declaring the proxies to start, instead of the single proxy above
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// JSON or TOML file with a scenario to run once the proxies are up. Can be given more than
+    /// once.
+    #[arg(long)]
+    pub scenario: Vec<PathBuf>,
+
+    /// Seed for the random decisions of toxics without a seed of their own
+    #[arg(long)]
+    pub seed: Option<u64>,
 }
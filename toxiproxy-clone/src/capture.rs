@@ -0,0 +1,324 @@
+use crate::toxic::Stream;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Serialize, Serializer};
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+
+// Records that can wait for the disk before new ones are dropped
+const CAPACITY: usize = 4096;
+
+// Something that happened on a connection, as a line of the transcript
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub(crate) enum Event<'a> {
+    // A client connected, or sent the first datagram of a UDP session
+    Open { client: &'a str },
+    // Data read from the sending side, before any toxic saw it
+    Received {
+        stream: Stream,
+        #[serde(serialize_with = "base64")]
+        data: &'a [u8],
+    },
+    // Data written to the receiving side, as the toxics left it
+    Sent {
+        stream: Stream,
+        #[serde(serialize_with = "base64")]
+        data: &'a [u8],
+    },
+    // The direction ended, and the EOF was passed on
+    Close { stream: Stream },
+    // Records left out of the transcript here, as the disk couldn't keep up
+    Dropped { records: u64 },
+}
+
+fn base64<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(data))
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    // Microseconds since the Unix epoch
+    time_us: u64,
+    proxy: &'a str,
+    // Id of the connection, as listed by the REST API and sent in events. Records about the
+    // transcript itself have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection: Option<u64>,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+// Transcript of the traffic of a proxy, appended to a JSON-lines file. Records are written on a
+// background task, so connections never wait on the disk. When it falls too far behind, records
+// are dropped and a `dropped` record marks the gap.
+#[derive(Clone)]
+pub(crate) struct Capture {
+    proxy: String,
+    lines: mpsc::Sender<String>,
+    // Records dropped since the last one that made it into the transcript
+    dropped: Arc<AtomicU64>,
+}
+
+impl Capture {
+    // Opens the file for appending, creating it if needed. A file that can't be opened is
+    // reported as `InvalidInput`, as it comes from the proxy's settings.
+    pub(crate) async fn open(proxy: &str, path: &Path) -> io::Result<Capture> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("can't open capture file {}: {}", path.display(), e),
+                )
+            })?;
+
+        let (lines, receiver) = mpsc::channel(CAPACITY);
+        tokio::spawn(write_lines(file, receiver));
+        Ok(Capture {
+            proxy: proxy.to_string(),
+            lines,
+            dropped: Arc::default(),
+        })
+    }
+
+    pub(crate) fn record(&self, connection: u64, event: Event) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 && !self.send(None, Event::Dropped { records: dropped }) {
+            self.dropped.fetch_add(dropped + 1, Ordering::Relaxed);
+            return;
+        }
+        if !self.send(Some(connection), event) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Queues the record for writing. Returns false if the queue is full.
+    fn send(&self, connection: Option<u64>, event: Event) -> bool {
+        let time_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let record = Record {
+            time_us,
+            proxy: &self.proxy,
+            connection,
+            event,
+        };
+        let mut line = serde_json::to_string(&record).expect("records serialize");
+        line.push('\n');
+        !matches!(self.lines.try_send(line), Err(mpsc::error::TrySendError::Full(_)))
+    }
+}
+
+// Writes records until every capture handle is gone, flushing whenever it catches up so the
+// transcript can be followed while connections are open
+async fn write_lines(mut file: File, mut lines: mpsc::Receiver<String>) {
+    while let Some(mut batch) = lines.recv().await {
+        while let Ok(line) = lines.try_recv() {
+            batch.push_str(&line);
+        }
+        let written = async {
+            file.write_all(batch.as_bytes()).await?;
+            file.flush().await
+        };
+        if let Err(e) = written.await {
+            eprintln!("Capture write failed: {}", e);
+            return;
+        }
+    }
+}
+
+// One half of a socket, recording what is read from or written to it when the proxy captures
+pub(crate) struct Tap<T> {
+    inner: T,
+    capture: Option<Capture>,
+    connection: u64,
+    stream: Stream,
+}
+
+impl<T> Tap<T> {
+    pub(crate) fn new(inner: T, capture: Option<Capture>, connection: u64, stream: Stream) -> Self {
+        Tap {
+            inner,
+            capture,
+            connection,
+            stream,
+        }
+    }
+
+    fn record(&self, event: Event) {
+        if let Some(capture) = &self.capture {
+            capture.record(self.connection, event);
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tap<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let tap = self.get_mut();
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut tap.inner).poll_read(cx, buf);
+        let data = &buf.filled()[before..];
+        if matches!(poll, Poll::Ready(Ok(()))) && !data.is_empty() {
+            tap.record(Event::Received {
+                stream: tap.stream,
+                data,
+            });
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tap<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let tap = self.get_mut();
+        let poll = Pin::new(&mut tap.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            tap.record(Event::Sent {
+                stream: tap.stream,
+                data: &buf[..n],
+            });
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let tap = self.get_mut();
+        let poll = Pin::new(&mut tap.inner).poll_shutdown(cx);
+        if matches!(poll, Poll::Ready(Ok(()))) {
+            tap.record(Event::Close { stream: tap.stream });
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn capture_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    // Waits for the background writer to catch up with `count` records
+    async fn read_records(path: &Path, count: usize) -> Vec<Value> {
+        for _ in 0..100 {
+            let records: Vec<Value> = std::fs::read_to_string(path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            if records.len() >= count {
+                return records;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("capture has fewer than {} records", count);
+    }
+
+    #[tokio::test]
+    async fn capture_writes_events_as_json_lines() {
+        let path = capture_path("capture-events");
+        let capture = Capture::open("main", &path).await.unwrap();
+        capture.record(
+            3,
+            Event::Open {
+                client: "127.0.0.1:5000",
+            },
+        );
+        capture.record(
+            3,
+            Event::Received {
+                stream: Stream::Upstream,
+                data: b"\x00ping",
+            },
+        );
+
+        let records = read_records(&path, 2).await;
+        assert_eq!(records[0]["event"], "open");
+        assert_eq!(records[0]["client"], "127.0.0.1:5000");
+        assert_eq!(records[1]["proxy"], "main");
+        assert_eq!(records[1]["connection"], 3);
+        assert_eq!(records[1]["stream"], "upstream");
+        assert_eq!(records[1]["data"], "AHBpbmc=");
+        assert!(records[1]["time_us"].as_u64().unwrap() > 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tap_records_reads_writes_and_shutdown() {
+        let path = capture_path("capture-tap");
+        let capture = Capture::open("main", &path).await.unwrap();
+
+        let mut reader = Tap::new(&b"ping"[..], Some(capture.clone()), 0, Stream::Upstream);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        let mut writer = Tap::new(Vec::new(), Some(capture), 0, Stream::Upstream);
+        writer.write_all(b"pong").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let records = read_records(&path, 3).await;
+        let events: Vec<&str> = records.iter().map(|r| r["event"].as_str().unwrap()).collect();
+        assert_eq!(events, ["received", "sent", "close"]);
+        assert_eq!(records[0]["data"], STANDARD.encode("ping"));
+        assert_eq!(records[1]["data"], STANDARD.encode("pong"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn capture_marks_records_dropped_while_full() {
+        let (lines, mut receiver) = mpsc::channel(2);
+        let capture = Capture {
+            proxy: "main".to_string(),
+            lines,
+            dropped: Arc::default(),
+        };
+        for connection in 0..5 {
+            capture.record(connection, Event::Close { stream: Stream::Upstream });
+        }
+        while receiver.try_recv().is_ok() {}
+
+        capture.record(5, Event::Close { stream: Stream::Upstream });
+        let gap: Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(gap["event"], "dropped");
+        assert_eq!(gap["records"], 3);
+        assert!(gap.get("connection").is_none());
+        let next: Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(next["connection"], 5);
+    }
+
+    #[tokio::test]
+    async fn capture_reports_unopenable_file_as_invalid_input() {
+        let path = Path::new("/nonexistent/capture.jsonl");
+        let error = Capture::open("main", path).await.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::events::{self, ProxyEvent};
+use crate::proxy::{Proxy, ProxyConfig};
+use crate::toxic::ToxicConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+// Proxies and their initial toxics, as read from a config file or `POST /populate`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub proxies: Vec<ProxyDefinition>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyDefinition {
+    #[serde(flatten)]
+    pub config: ProxyConfig,
+    #[serde(default)]
+    pub toxics: Vec<ToxicConfig>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Config {
+    // Reads a TOML file when the extension says so, and JSON otherwise
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let contents = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|extension| extension == "toml") {
+            toml::from_str(&contents).map_err(|e| invalid(e.to_string()))
+        } else {
+            serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))
+        }
+    }
+
+    // Replaces every proxy with the ones declared here. The new proxies are only swapped in
+    // once all of them are listening; otherwise the old ones are restarted and left in place.
+    // Configuration errors are reported as `InvalidInput`, before anything is stopped.
+    pub async fn apply(self, proxies: &mut HashMap<String, Proxy>) -> io::Result<()> {
+        let mut created = Vec::new();
+        let mut names = HashSet::new();
+        for definition in self.proxies {
+            if !names.insert(definition.config.name.clone()) {
+                return Err(invalid(format!("duplicate proxy: {}", definition.config.name)));
+            }
+
+            let proxy = Proxy::new(definition.config);
+            for toxic in definition.toxics {
+                proxy.check_toxic(&toxic).map_err(invalid)?;
+                if proxy.add_toxic(toxic).is_none() {
+                    return Err(invalid(format!("duplicate toxic on proxy {}", proxy.name)));
+                }
+            }
+            created.push(proxy);
+        }
+
+        // Free the listen addresses first, a new proxy may take over the address of an old one
+        for proxy in proxies.values_mut() {
+            proxy.stop().await;
+        }
+
+        let mut started: Vec<Proxy> = Vec::new();
+        for mut proxy in created {
+            if let Err(e) = proxy.start().await {
+                for mut proxy in started {
+                    proxy.stop().await;
+                }
+                for proxy in proxies.values_mut() {
+                    if let Err(e) = proxy.start().await {
+                        eprintln!("Failed to restart proxy {}: {}", proxy.name, e);
+                    }
+                }
+                return Err(e);
+            }
+            started.push(proxy);
+        }
+
+        // The replaced proxies are gone for good, so their connections go with them
+        for (name, proxy) in proxies.iter() {
+            proxy.close_connections();
+            events::publish(name, ProxyEvent::ProxyDeleted);
+        }
+        for proxy in &started {
+            events::publish(&proxy.name, ProxyEvent::ProxyCreated);
+        }
+        *proxies = started
+            .into_iter()
+            .map(|proxy| (proxy.name.clone(), proxy))
+            .collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::Protocol;
+    use crate::toxic::{Jitter, Stream, ToxicKind};
+
+    fn definition(name: &str) -> ProxyDefinition {
+        ProxyDefinition {
+            config: ProxyConfig {
+                name: name.to_string(),
+                listen: "127.0.0.1:0".to_string(),
+                upstream: "127.0.0.1:8476".to_string(),
+                protocol: Protocol::Tcp,
+                enabled: true,
+                tls: None,
+                capture: None,
+            },
+            toxics: vec![ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE }.into()],
+        }
+    }
+
+    #[test]
+    fn config_reads_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            [[proxies]]
+            name = "postgres"
+            listen = "127.0.0.1:15432"
+            upstream = "127.0.0.1:5432"
+
+            [[proxies.toxics]]
+            type = "Latency"
+            latency_ms = 100
+            stream = "upstream"
+            "#,
+        )
+        .unwrap();
+
+        let proxy = &config.proxies[0];
+        assert_eq!(proxy.config.name, "postgres");
+        assert!(proxy.config.enabled);
+        assert_eq!(proxy.toxics[0].stream, Stream::Upstream);
+        assert!(matches!(
+            proxy.toxics[0].kind,
+            ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE }
+        ));
+    }
+
+    #[test]
+    fn config_reads_json() {
+        let config: Config = serde_json::from_str(
+            r#"{"proxies": [{"name": "redis", "listen": "127.0.0.1:16379",
+                "upstream": "127.0.0.1:6379", "enabled": false,
+                "toxics": [{"type": "Timeout", "timeout_ms": 0}]}]}"#,
+        )
+        .unwrap();
+
+        assert!(!config.proxies[0].config.enabled);
+        assert_eq!(config.proxies[0].toxics.len(), 1);
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let config: Config = toml::from_str(include_str!("../config/example.toml")).unwrap();
+        assert!(!config.proxies.is_empty());
+        assert!(matches!(
+            config.proxies[1].toxics[0].kind,
+            ToxicKind::Latency { jitter: Jitter { jitter_ms: 50, .. }, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn apply_replaces_proxies() {
+        let mut proxies = HashMap::new();
+        Config {
+            proxies: vec![definition("old")],
+        }
+        .apply(&mut proxies)
+        .await
+        .unwrap();
+        let old_listen = proxies["old"].listen.clone();
+
+        Config {
+            proxies: vec![definition("a"), definition("b")],
+        }
+        .apply(&mut proxies)
+        .await
+        .unwrap();
+
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(proxies["a"].toxics.borrow().len(), 1);
+        assert!(tokio::net::TcpStream::connect(&proxies["b"].listen).await.is_ok());
+        assert!(tokio::net::TcpStream::connect(&old_listen).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn apply_rejects_invalid_config_without_changes() {
+        let mut proxies = HashMap::new();
+        Config {
+            proxies: vec![definition("old")],
+        }
+        .apply(&mut proxies)
+        .await
+        .unwrap();
+
+        let result = Config {
+            proxies: vec![definition("a"), definition("a")],
+        }
+        .apply(&mut proxies)
+        .await;
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(proxies.contains_key("old"));
+        assert!(tokio::net::TcpStream::connect(&proxies["old"].listen).await.is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::toxic::{Stream, ToxicConfig};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+// Events a subscriber can fall behind by before it misses some
+const CAPACITY: usize = 1024;
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<EventRecord> = broadcast::channel(CAPACITY).0;
+}
+
+// Something that happened to a proxy, as streamed by `GET /events`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProxyEvent {
+    ProxyCreated,
+    ProxyDeleted,
+    ToxicAdded { toxic: ToxicConfig },
+    ToxicUpdated { toxic: ToxicConfig },
+    ToxicRemoved { toxic: String },
+    ConnectionOpened { connection: u64, client: String },
+    ConnectionClosed { connection: u64 },
+    // The toxicity roll of a toxic came up for a connection, so it now applies there
+    ToxicActivated {
+        connection: u64,
+        toxic: String,
+        stream: Stream,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventRecord {
+    // Microseconds since the Unix epoch
+    pub time_us: u64,
+    pub proxy: String,
+    #[serde(flatten)]
+    pub event: ProxyEvent,
+}
+
+// Sends the event to every subscriber. Without subscribers it goes nowhere.
+pub fn publish(proxy: &str, event: ProxyEvent) {
+    if EVENTS.receiver_count() == 0 {
+        return;
+    }
+    let time_us = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let _ = EVENTS.send(EventRecord {
+        time_us,
+        proxy: proxy.to_string(),
+        event,
+    });
+}
+
+// Receives every event published from now on
+pub fn subscribe() -> broadcast::Receiver<EventRecord> {
+    EVENTS.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_receive_published_events() {
+        let mut events = subscribe();
+        publish("events_test", ProxyEvent::ProxyCreated);
+
+        // Other tests publish too, so look for ours
+        loop {
+            let record = events.recv().await.unwrap();
+            if record.proxy == "events_test" {
+                assert!(matches!(record.event, ProxyEvent::ProxyCreated));
+                assert!(record.time_us > 0);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn event_record_serializes_flat() {
+        let record = EventRecord {
+            time_us: 1,
+            proxy: "main".to_string(),
+            event: ProxyEvent::ToxicActivated {
+                connection: 3,
+                toxic: "lag".to_string(),
+                stream: Stream::Upstream,
+            },
+        };
+        let value = serde_json::to_value(&record).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"time_us": 1, "proxy": "main", "event": "toxic_activated",
+                "connection": 3, "toxic": "lag", "stream": "upstream"})
+        );
+    }
+}
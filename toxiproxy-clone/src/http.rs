@@ -0,0 +1,407 @@
+use axum::http::StatusCode;
+use std::io;
+
+const MAX_HEADERS: usize = 100;
+
+// An HTTP/1.x request as it passes through an HTTP proxy. The proxy streams request bodies
+// through rather than holding on to them, so the requests toxics see have an empty body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    // Request target as sent by the client, including the query
+    pub path: String,
+    // Minor version, 0 for HTTP/1.0 and 1 for HTTP/1.1
+    pub version: u8,
+    pub headers: Vec<(String, String)>,
+    // Body as framed on the wire, so chunked bodies keep their chunks
+    pub body: Vec<u8>,
+}
+
+// An HTTP/1.x response, either from the server or injected by a toxic. Responses from the server
+// stream through like requests, so theirs have an empty body too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub version: u8,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+fn invalid(message: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn remove_header(headers: &mut Vec<(String, String)>, name: &str) {
+    headers.retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+}
+
+fn read_headers(headers: &[httparse::Header]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|header| {
+            let value = String::from_utf8_lossy(header.value).into_owned();
+            (header.name.to_string(), value)
+        })
+        .collect()
+}
+
+fn write_head(start_line: String, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    let mut data = start_line.into_bytes();
+    data.extend_from_slice(b"\r\n");
+    for (name, value) in headers {
+        data.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    data.extend_from_slice(b"\r\n");
+    data.extend_from_slice(body);
+    data
+}
+
+// Length of the body at the start of `data`, if it is all there
+fn body_length(headers: &[(String, String)], data: &[u8]) -> io::Result<Option<usize>> {
+    let mut body = Body::new(headers)?;
+    let length = body.advance(data)?;
+    Ok(body.is_done().then_some(length))
+}
+
+// Follows a message body as it goes past in pieces, to tell where it ends without holding on to
+// it. Chunked bodies are followed chunk by chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Body {
+    // Bytes still to come, of a body with a length or of a chunk and its trailing CRLF
+    Length(u64),
+    Chunk(u64),
+    // Waiting for the size line of the next chunk
+    ChunkSize,
+    // After the last chunk, waiting for the optional trailers and the empty line ending them
+    Trailers,
+    // A response without a length, which ends when the server closes the connection
+    UntilEof,
+    Done,
+}
+
+impl Body {
+    // The body following a head with these headers
+    pub fn new(headers: &[(String, String)]) -> io::Result<Body> {
+        let chunked = header(headers, "transfer-encoding")
+            .is_some_and(|encoding| encoding.to_ascii_lowercase().ends_with("chunked"));
+        if chunked {
+            return Ok(Body::ChunkSize);
+        }
+
+        let length = match header(headers, "content-length") {
+            Some(length) => length
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| invalid(format!("invalid content length: {}", length)))?,
+            None => 0,
+        };
+        Ok(if length == 0 { Body::Done } else { Body::Length(length) })
+    }
+
+    // The body following the head of `response`. Responses to HEAD requests have none.
+    pub fn of_response(response: &HttpResponse, head_request: bool) -> io::Result<Body> {
+        let status = response.status;
+        if head_request || status < 200 || status == 204 || status == 304 {
+            return Ok(Body::Done);
+        }
+        if response.header("transfer-encoding").is_none()
+            && response.header("content-length").is_none()
+        {
+            return Ok(Body::UntilEof);
+        }
+        Body::new(&response.headers)
+    }
+
+    pub fn is_done(&self) -> bool {
+        *self == Body::Done
+    }
+
+    // Moves past the part of `data` that belongs to the body, returning its length. Whatever
+    // is left of `data` is either what comes after the body, or the start of a chunk size line
+    // or of trailers that need more data to be told apart.
+    pub fn advance(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut position = 0;
+        loop {
+            let rest = &data[position..];
+            match *self {
+                Body::Done => return Ok(position),
+                Body::UntilEof => return Ok(data.len()),
+                Body::Length(remaining) | Body::Chunk(remaining) => {
+                    let taken = remaining.min(rest.len() as u64);
+                    position += taken as usize;
+                    *self = match *self {
+                        Body::Length(_) if taken < remaining => Body::Length(remaining - taken),
+                        Body::Chunk(_) if taken < remaining => Body::Chunk(remaining - taken),
+                        Body::Length(_) => Body::Done,
+                        _ => Body::ChunkSize,
+                    };
+                    if taken < remaining {
+                        return Ok(position);
+                    }
+                }
+                Body::ChunkSize => {
+                    let (consumed, size) = match httparse::parse_chunk_size(rest) {
+                        Ok(httparse::Status::Complete(chunk)) => chunk,
+                        Ok(httparse::Status::Partial) => return Ok(position),
+                        Err(_) => return Err(invalid("invalid chunk size")),
+                    };
+                    position += consumed;
+                    *self = match size {
+                        0 => Body::Trailers,
+                        // Chunk data and its trailing CRLF
+                        size => Body::Chunk(
+                            size.checked_add(2).ok_or_else(|| invalid("chunk too large"))?,
+                        ),
+                    };
+                }
+                Body::Trailers => {
+                    let end = if rest.starts_with(b"\r\n") {
+                        Some(2)
+                    } else {
+                        rest.windows(4)
+                            .position(|window| window == b"\r\n\r\n")
+                            .map(|end| end + 4)
+                    };
+                    let Some(end) = end else {
+                        return Ok(position);
+                    };
+                    position += end;
+                    *self = Body::Done;
+                }
+            }
+        }
+    }
+}
+
+impl HttpRequest {
+    // Parses the request at the start of `data`. Returns it with the number of bytes it took up,
+    // or `None` if it isn't complete yet.
+    pub fn parse(data: &[u8]) -> io::Result<Option<(HttpRequest, usize)>> {
+        let Some((mut request, head)) = Self::parse_head(data)? else {
+            return Ok(None);
+        };
+        let Some(length) = body_length(&request.headers, &data[head..])? else {
+            return Ok(None);
+        };
+        request.body = data[head..head + length].to_vec();
+        Ok(Some((request, head + length)))
+    }
+
+    // Parses just the head of the request at the start of `data`, leaving the body empty.
+    // Returns it with the length of the head, or `None` if the head isn't complete yet.
+    pub fn parse_head(data: &[u8]) -> io::Result<Option<(HttpRequest, usize)>> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        let head = match request.parse(data).map_err(invalid)? {
+            httparse::Status::Complete(head) => head,
+            httparse::Status::Partial => return Ok(None),
+        };
+
+        let request = HttpRequest {
+            method: request.method.unwrap_or_default().to_string(),
+            path: request.path.unwrap_or_default().to_string(),
+            version: request.version.unwrap_or(1),
+            headers: read_headers(request.headers),
+            body: Vec::new(),
+        };
+        Ok(Some((request, head)))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        remove_header(&mut self.headers, name)
+    }
+
+    // Whether the path starts with `prefix`. An empty prefix matches every request.
+    pub fn matches(&self, prefix: &str) -> bool {
+        self.path.starts_with(prefix)
+    }
+
+    // Whether the connection stops speaking HTTP after this request, as for WebSockets
+    pub fn is_upgrade(&self) -> bool {
+        self.method == "CONNECT" || self.header("upgrade").is_some()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let start_line = format!("{} {} HTTP/1.{}", self.method, self.path, self.version);
+        write_head(start_line, &self.headers, &self.body)
+    }
+}
+
+impl HttpResponse {
+    // A response with a plain text body, as injected by toxics
+    pub fn new(status: u16, body: &str) -> Self {
+        let reason = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default();
+        let mut headers = vec![("Content-Length".to_string(), body.len().to_string())];
+        if !body.is_empty() {
+            headers.push(("Content-Type".to_string(), "text/plain".to_string()));
+        }
+        HttpResponse {
+            version: 1,
+            status,
+            reason: reason.to_string(),
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    // Parses the response at the start of `data`, like `HttpRequest::parse`. Responses to HEAD
+    // requests have no body, and responses without a length end when the server closes, which
+    // `eof` tells.
+    pub fn parse(
+        data: &[u8],
+        head_request: bool,
+        eof: bool,
+    ) -> io::Result<Option<(HttpResponse, usize)>> {
+        let Some((mut response, head)) = Self::parse_head(data)? else {
+            return Ok(None);
+        };
+        let mut body = Body::of_response(&response, head_request)?;
+        let length = body.advance(&data[head..])?;
+        let complete = body.is_done() || (eof && body == Body::UntilEof);
+        if !complete {
+            return Ok(None);
+        }
+        response.body = data[head..head + length].to_vec();
+        Ok(Some((response, head + length)))
+    }
+
+    // Parses just the head of the response at the start of `data`, like
+    // `HttpRequest::parse_head`
+    pub fn parse_head(data: &[u8]) -> io::Result<Option<(HttpResponse, usize)>> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        let head = match response.parse(data).map_err(invalid)? {
+            httparse::Status::Complete(head) => head,
+            httparse::Status::Partial => return Ok(None),
+        };
+
+        let response = HttpResponse {
+            version: response.version.unwrap_or(1),
+            status: response.code.unwrap_or_default(),
+            reason: response.reason.unwrap_or_default().to_string(),
+            headers: read_headers(response.headers),
+            body: Vec::new(),
+        };
+        Ok(Some((response, head)))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        remove_header(&mut self.headers, name)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let start_line = format!("HTTP/1.{} {} {}", self.version, self.status, self.reason);
+        write_head(start_line, &self.headers, &self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_parse_waits_for_whole_body() {
+        let data = b"POST /orders HTTP/1.1\r\nHost: shop\r\nContent-Length: 5\r\n\r\nab";
+        assert_eq!(HttpRequest::parse(data).unwrap(), None);
+
+        let data = b"POST /orders HTTP/1.1\r\nHost: shop\r\nContent-Length: 5\r\n\r\nabcdeGET";
+        let (request, length) = HttpRequest::parse(data).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/orders");
+        assert_eq!(request.header("host"), Some("shop"));
+        assert_eq!(request.body, b"abcde");
+        assert_eq!(length, data.len() - 3);
+    }
+
+    #[test]
+    fn request_parse_reads_chunked_body() {
+        let data = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+        let (request, length) = HttpRequest::parse(data).unwrap().unwrap();
+        assert_eq!(request.body, b"3\r\nabc\r\n0\r\n\r\n");
+        assert_eq!(length, data.len());
+
+        assert_eq!(HttpRequest::parse(&data[..data.len() - 2]).unwrap(), None);
+    }
+
+    #[test]
+    fn body_follows_chunks_across_pieces() {
+        let data = b"3\r\nabc\r\n10\r\n0123456789abcdef\r\n0\r\nX-Sum: 1\r\n\r\nGET";
+        let mut body = Body::ChunkSize;
+        let mut length = 0;
+        // Feeds the body a byte at a time, with whatever it leaves for later in front
+        for end in 1..=data.len() {
+            length += body.advance(&data[length..end]).unwrap();
+        }
+        assert!(body.is_done());
+        assert_eq!(length, data.len() - 3);
+    }
+
+    #[test]
+    fn body_stops_at_content_length() {
+        let headers = [("Content-Length".to_string(), "5".to_string())];
+        let mut body = Body::new(&headers).unwrap();
+        assert_eq!(body.advance(b"abc").unwrap(), 3);
+        assert!(!body.is_done());
+        assert_eq!(body.advance(b"deGET").unwrap(), 2);
+        assert!(body.is_done());
+        assert!(Body::new(&[]).unwrap().is_done());
+    }
+
+    #[test]
+    fn request_parse_rejects_garbage() {
+        assert!(HttpRequest::parse(b"\x16\x03\x01 hello\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn request_round_trips_through_bytes() {
+        let data = b"GET /health?full=1 HTTP/1.1\r\nHost: shop\r\n\r\n";
+        let (request, _) = HttpRequest::parse(data).unwrap().unwrap();
+        assert_eq!(request.to_bytes(), data);
+    }
+
+    #[test]
+    fn response_parse_ends_unframed_body_at_eof() {
+        let data = b"HTTP/1.0 200 OK\r\n\r\nhello";
+        assert_eq!(HttpResponse::parse(data, false, false).unwrap(), None);
+
+        let (response, length) = HttpResponse::parse(data, false, true).unwrap().unwrap();
+        assert_eq!(response.body, b"hello");
+        assert_eq!(length, data.len());
+    }
+
+    #[test]
+    fn response_parse_skips_body_of_head_requests() {
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+        let (response, length) = HttpResponse::parse(data, true, false).unwrap().unwrap();
+        assert!(response.body.is_empty());
+        assert_eq!(length, data.len());
+    }
+
+    #[test]
+    fn response_new_sets_reason_and_length() {
+        let response = HttpResponse::new(503, "down");
+        assert_eq!(
+            response.to_bytes(),
+            b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\nContent-Type: text/plain\r\n\r\ndown"
+        );
+    }
+}
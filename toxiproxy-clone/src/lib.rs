@@ -0,0 +1,12 @@
+pub mod capture;
+pub mod config;
+pub mod events;
+pub mod http;
+pub mod metrics;
+pub mod net;
+pub mod proxy;
+pub mod rest_api;
+pub mod scenario;
+pub mod tls;
+pub mod toxic;
+pub mod toxics;
@@ -1,51 +1,75 @@
 mod args;
-mod proxy;
-mod rest_api;
-mod toxic;
-mod toxics;
 
 use crate::args::Args;
-use crate::proxy::Proxy;
-use crate::toxics::corrupt::CorruptToxic;
-use crate::toxics::latency::LatencyToxic;
-use crate::toxics::slow_close::SlowCloseToxic;
 use clap::Parser;
 use std::{
+    collections::HashMap,
     io::{self},
     sync::Arc,
-    time::Duration,
 };
+use toxiproxy_clone::config::{Config, ProxyDefinition};
+use toxiproxy_clone::proxy::{Protocol, ProxyConfig, ProxyState};
+use toxiproxy_clone::rest_api;
+use toxiproxy_clone::scenario::{Scenario, Scenarios};
+use toxiproxy_clone::toxic;
 
-fn main() -> io::Result<()> {
+#[tokio::main]
+async fn main() -> io::Result<()> {
     let args = Args::parse();
+    if let Some(seed) = args.seed {
+        toxic::set_global_seed(seed);
+    }
 
-    // let api_address = format!("{}:{}", args.host, args.api_port);
+    let api_address = format!("{}:{}", args.host, args.api_port);
     let proxy_address = format!("{}:{}", args.host, args.proxy_port);
     let upstream_address = format!("{}:{}", args.upstream_host, args.upstream_port);
 
-    // let proxy_state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+    let proxy_state: ProxyState = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
 
     println!("Starting Toxiproxy...");
-    // println!("REST API listening on: {}", api_address);
-    println!("Proxy listening on: {}", proxy_address);
-    println!("Forwarding to upstream: {}", upstream_address);
-    // println!("Prometheus metrics available at: http://{}/metrics", api_address);
-    // println!("Proxy metrics available at: http://{}/metrics/proxy", api_address);
-
-    let mut proxy = Proxy::new();
-
-    // Add some example toxics
-    proxy.add_toxic(Arc::new(LatencyToxic {
-        latency: Duration::from_millis(100),
-    }));
-    proxy.add_toxic(Arc::new(CorruptToxic { probability: 0.01 }));
-    proxy.add_toxic(Arc::new(SlowCloseToxic {
-        delay: Duration::from_secs(1),
-    }));
-
-    // Start the proxy
-    proxy.start(proxy_address.as_str(), upstream_address.as_str())
-        .expect("Unable to start proxy");
-
-    Ok(())
+    // Bind the API first, so a taken port fails startup before any proxy or scenario runs
+    let listener = tokio::net::TcpListener::bind(&api_address).await?;
+    let api_address = listener.local_addr()?;
+    println!("REST API listening on: {}", api_address);
+
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config {
+            proxies: vec![ProxyDefinition {
+                config: ProxyConfig {
+                    name: "main".to_string(),
+                    listen: proxy_address,
+                    upstream: upstream_address,
+                    protocol: Protocol::Tcp,
+                    enabled: true,
+                    tls: None,
+                    capture: None,
+                },
+                toxics: Vec::new(),
+            }],
+        },
+    };
+    config.apply(&mut *proxy_state.lock().await).await?;
+
+    for proxy in proxy_state.lock().await.values() {
+        if !proxy.enabled {
+            println!("Proxy {} is disabled", proxy.name);
+            continue;
+        }
+        println!(
+            "Proxy {} listening on: {}, forwarding to upstream: {}",
+            proxy.name, proxy.listen, proxy.upstream
+        );
+    }
+    let scenarios = Scenarios::default();
+    for path in &args.scenario {
+        let scenario = Scenario::load(path)?;
+        let name = scenario.name.clone();
+        scenarios.start(scenario, &proxy_state).await?;
+        println!("Scenario {} started", name);
+    }
+
+    println!("Prometheus metrics available at: http://{}/metrics", api_address);
+
+    axum::serve(listener, rest_api::router(proxy_state, scenarios)).await
 }
@@ -0,0 +1,91 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+// Metrics definitions
+lazy_static! {
+    pub static ref BYTES_TRANSFERRED: IntCounterVec = register_int_counter_vec!(
+        "toxiproxy_bytes_transferred",
+        "Total bytes transferred through proxy",
+        &["proxy", "direction"]
+    )
+    .unwrap();
+
+    pub static ref ACTIVE_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "toxiproxy_active_connections",
+        "Number of active connections",
+        &["proxy"]
+    )
+    .unwrap();
+
+    pub static ref TOXIC_ACTIVATIONS: IntCounterVec = register_int_counter_vec!(
+        "toxiproxy_toxic_activations",
+        "Number of times each toxic was activated",
+        &["proxy", "toxic_type", "direction"]
+    )
+    .unwrap();
+
+    pub static ref LATENCY_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "toxiproxy_latency_seconds",
+        "Time data spent passing through toxics",
+        &["proxy", "direction"],
+        vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]
+    )
+    .unwrap();
+}
+
+// Counts a connection as active for as long as it is alive
+pub struct ActiveConnection {
+    proxy: String,
+}
+
+impl ActiveConnection {
+    pub fn new(proxy: &str) -> Self {
+        ACTIVE_CONNECTIONS.with_label_values(&[proxy]).inc();
+        ActiveConnection {
+            proxy: proxy.to_string(),
+        }
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        ACTIVE_CONNECTIONS.with_label_values(&[&self.proxy]).dec();
+    }
+}
+
+// Renders every metric in the Prometheus text format
+pub fn encode() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_connection_is_counted_until_dropped() {
+        let gauge = ACTIVE_CONNECTIONS.with_label_values(&["metrics_test"]);
+        let connection = ActiveConnection::new("metrics_test");
+        assert_eq!(gauge.get(), 1);
+        drop(connection);
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[test]
+    fn encode_renders_registered_metrics() {
+        BYTES_TRANSFERRED
+            .with_label_values(&["metrics_test", "upstream"])
+            .inc_by(10);
+        let text = encode();
+        assert!(text.contains(
+            r#"toxiproxy_bytes_transferred{direction="upstream",proxy="metrics_test"} 10"#
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+// Prefix of the addresses of Unix domain sockets, as in `unix:/run/postgresql/.s.PGSQL.5432`
+const UNIX_PREFIX: &str = "unix:";
+
+// Path of the socket for a `unix:` address, `None` for a TCP address
+pub(crate) fn unix_path(address: &str) -> Option<&Path> {
+    address.strip_prefix(UNIX_PREFIX).map(Path::new)
+}
+
+// A socket accepting connections, on a TCP port or a Unix socket path
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    // Binds the address. A socket file that nothing listens on anymore, as left behind by a
+    // process that didn't shut down cleanly, is replaced.
+    pub(crate) async fn bind(address: &str) -> io::Result<Listener> {
+        let Some(path) = unix_path(address) else {
+            return Ok(Listener::Tcp(TcpListener::bind(address).await?));
+        };
+        let is_socket =
+            std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket());
+        if is_socket && UnixStream::connect(path).await.is_err() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(Listener::Unix(UnixListener::bind(path)?, path.to_path_buf()))
+    }
+
+    // The bound address, with the port actually assigned for a TCP port of 0
+    pub(crate) fn local_addr(&self) -> io::Result<String> {
+        match self {
+            Listener::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            Listener::Unix(_, path) => Ok(format!("{}{}", UNIX_PREFIX, path.display())),
+        }
+    }
+
+    // Accepts a connection, along with the address of the peer. Clients of Unix sockets rarely
+    // bind a path of their own, so theirs is usually just `unix:`.
+    pub(crate) async fn accept(&self) -> io::Result<(NetStream, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((NetStream::Tcp(stream), peer.to_string()))
+            }
+            Listener::Unix(listener, _) => {
+                let (stream, peer) = listener.accept().await?;
+                let path = peer.as_pathname().map(Path::display);
+                let peer = match path {
+                    Some(path) => format!("{}{}", UNIX_PREFIX, path),
+                    None => UNIX_PREFIX.to_string(),
+                };
+                Ok((NetStream::Unix(stream), peer))
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    // Unix sockets leave their file behind, which would keep the path from being bound again
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// A connection to either side of a proxy, over TCP or a Unix socket
+pub(crate) enum NetStream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl NetStream {
+    pub(crate) async fn connect(address: &str) -> io::Result<NetStream> {
+        match unix_path(address) {
+            Some(path) => Ok(NetStream::Unix(UnixStream::connect(path).await?)),
+            None => Ok(NetStream::Tcp(TcpStream::connect(address).await?)),
+        }
+    }
+
+    // Makes closing the socket abort the connection. Unix sockets have no resets, so they are
+    // closed as usual.
+    pub(crate) fn set_zero_linger(&self) -> io::Result<()> {
+        match self {
+            NetStream::Tcp(stream) => stream.set_zero_linger(),
+            NetStream::Unix(_) => Ok(()),
+        }
+    }
+}
+
+impl AsyncRead for NetStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NetStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            NetStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for NetStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            NetStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            NetStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NetStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            NetStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NetStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            NetStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn unix_path_reads_unix_addresses_only() {
+        assert_eq!(unix_path("unix:/tmp/pg.sock"), Some(Path::new("/tmp/pg.sock")));
+        assert_eq!(unix_path("127.0.0.1:5432"), None);
+    }
+
+    #[tokio::test]
+    async fn unix_listener_accepts_and_removes_its_socket_file() {
+        let path = socket_path("net-accept");
+        let address = format!("unix:{}", path.display());
+        let listener = Listener::bind(&address).await.unwrap();
+        assert_eq!(listener.local_addr().unwrap(), address);
+
+        let mut client = NetStream::connect(&address).await.unwrap();
+        let (mut server, peer) = listener.accept().await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut received = [0; 4];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ping");
+        assert_eq!(peer, "unix:");
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn unix_listener_replaces_stale_socket_but_not_other_files() {
+        let path = socket_path("net-stale");
+        let address = format!("unix:{}", path.display());
+        // A socket nobody accepts on anymore, as after a crash
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(Listener::bind(&address).await.is_ok());
+
+        let path = socket_path("net-file");
+        std::fs::write(&path, "data").unwrap();
+        let address = format!("unix:{}", path.display());
+        assert!(Listener::bind(&address).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,100 +1,1926 @@
-use crate::toxic::Toxic;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
-use std::{io, thread};
+mod http;
+mod udp;
+
+use crate::capture::{Capture, Event, Tap};
+use crate::events::{self, ProxyEvent};
+use crate::metrics::{self, ActiveConnection};
+use crate::net::{self, Listener, NetStream};
+use crate::tls::{Tls, TlsConfig};
+use crate::toxic::{Link, Stream, Toxic, ToxicConfig, ToxicState, ToxicWrapper};
+use crate::toxics;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use futures::future;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+
+// Toxics of a proxy. Open connections subscribe to them, so changes made through the
+// REST API reach connections that are already established.
+pub type Toxics = watch::Sender<Vec<Arc<ToxicWrapper>>>;
+type ToxicsReceiver = watch::Receiver<Vec<Arc<ToxicWrapper>>>;
+
+// Open connections of a proxy by id, so they can be listed and closed from outside their task
+type Connections = Arc<Mutex<HashMap<u64, OpenConnection>>>;
+
+// Ids of connections and UDP sessions, across all proxies. The same id is listed by the REST API,
+// sent in events and recorded in captures, and is never reused, not even across restarts.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+struct OpenConnection {
+    task: AbortHandle,
+    stats: Arc<ConnectionStats>,
+}
+
+// What a connection reports about itself while it is open, updated by its task
+struct ConnectionStats {
+    id: u64,
+    proxy: String,
+    // Peer address, `unix:` and the path for Unix sockets
+    client: String,
+    started: SystemTime,
+    // Bytes written to the receiving side, as the toxics left them, by direction
+    bytes: [AtomicU64; 2],
+    // Names of the toxics whose toxicity roll came up, by direction
+    toxics: Mutex<[Vec<String>; 2]>,
+}
+
+impl ConnectionStats {
+    fn new(proxy: &str, client: String) -> Self {
+        ConnectionStats {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            proxy: proxy.to_string(),
+            client,
+            started: SystemTime::now(),
+            bytes: Default::default(),
+            toxics: Default::default(),
+        }
+    }
+
+    fn slot(stream: Stream) -> usize {
+        match stream {
+            Stream::Upstream => 0,
+            Stream::Downstream => 1,
+        }
+    }
+
+    // Counts bytes written to the receiving side, for the connection and the proxy's metrics
+    fn forwarded(&self, stream: Stream, bytes: usize) {
+        self.bytes[Self::slot(stream)].fetch_add(bytes as u64, Ordering::Relaxed);
+        metrics::BYTES_TRANSFERRED
+            .with_label_values(&[&self.proxy, &stream.to_string()])
+            .inc_by(bytes as u64);
+    }
+
+    // Records the toxics that apply in a direction, publishing the ones that didn't before
+    fn set_toxics(&self, stream: Stream, active: &[ActiveToxic]) {
+        let names: Vec<String> = active
+            .iter()
+            .filter(|active| active.enabled)
+            .map(|active| active.toxic.name.clone())
+            .collect();
+        let mut toxics = self.toxics.lock().unwrap();
+        let previous = &toxics[Self::slot(stream)];
+        for name in names.iter().filter(|name| !previous.contains(name)) {
+            let activated = ProxyEvent::ToxicActivated {
+                connection: self.id,
+                toxic: name.clone(),
+                stream,
+            };
+            events::publish(&self.proxy, activated);
+        }
+        toxics[Self::slot(stream)] = names;
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        let started_us = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        ConnectionInfo {
+            id: self.id,
+            client: self.client.clone(),
+            started_us,
+            bytes_upstream: self.bytes[0].load(Ordering::Relaxed),
+            bytes_downstream: self.bytes[1].load(Ordering::Relaxed),
+            toxics: self.toxics.lock().unwrap().concat(),
+        }
+    }
+}
+
+// An open connection, as listed by the REST API
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub client: String,
+    // Microseconds since the Unix epoch
+    pub started_us: u64,
+    // Bytes passed on in each direction, as the toxics left them
+    pub bytes_upstream: u64,
+    pub bytes_downstream: u64,
+    // Toxics applying to the connection, upstream ones first
+    pub toxics: Vec<String>,
+}
+
+// Removes a connection from its proxy when its task ends, including when it is aborted
+struct Registration {
+    connections: Connections,
+    stats: Arc<ConnectionStats>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let id = self.stats.id;
+        self.connections.lock().unwrap().remove(&id);
+        events::publish(&self.stats.proxy, ProxyEvent::ConnectionClosed { connection: id });
+    }
+}
+
+// Spawns the task of a connection, registered with its proxy until the task ends
+fn spawn_connection<F>(connections: &Connections, stats: Arc<ConnectionStats>, connection: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let opened = ProxyEvent::ConnectionOpened {
+        connection: stats.id,
+        client: stats.client.clone(),
+    };
+    events::publish(&stats.proxy, opened);
+    let registration = Registration {
+        connections: Arc::clone(connections),
+        stats: Arc::clone(&stats),
+    };
+
+    // Hold the lock until the connection is registered, so it can't deregister first
+    let mut open = connections.lock().unwrap();
+    let id = stats.id;
+    let task = tokio::spawn(async move {
+        let _registration = registration;
+        connection.await
+    });
+    let task = task.abort_handle();
+    open.insert(id, OpenConnection { task, stats });
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+    // TCP with HTTP/1.x requests and responses parsed, for toxics that work on whole messages
+    Http,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+            Protocol::Http => write!(f, "http"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    pub name: String,
+    pub listen: String,
+    pub upstream: String,
+    #[serde(default)]
+    pub protocol: Protocol,
+    // A disabled proxy keeps its configuration and toxics, but doesn't listen
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    // JSON-lines file to append a transcript of every connection's traffic to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<PathBuf>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// Where a direction of a connection reads its data from
+#[async_trait]
+trait Source: Send {
+    // Reads the next piece of data, or `None` at EOF. It is raced against toxic changes, so it
+    // must not lose data when cancelled.
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>>;
+
+    // Runs the toxics that work on whole messages, returning the data to pass on
+    async fn process(&mut self, data: Vec<u8>, _toxics: &[&dyn Toxic]) -> io::Result<Vec<u8>> {
+        Ok(data)
+    }
+}
+
+// Reads data in chunks as it arrives
+struct RawSource<R> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R> RawSource<R> {
+    fn new(reader: R) -> Self {
+        RawSource {
+            reader,
+            buffer: vec![0; 4096],
+        }
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> Source for RawSource<R> {
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let n = self.reader.read(&mut self.buffer).await?;
+        Ok((n > 0).then(|| self.buffer[..n].to_vec()))
+    }
+}
+
+// A toxic on a connection, with the state it keeps there
+struct ActiveToxic {
+    toxic: Arc<ToxicWrapper>,
+    // Whether the toxicity roll for this connection came up
+    enabled: bool,
+    state: ToxicState,
+}
+
+// Passes data through the delivery hooks of the remaining toxics, then writes it to the socket
+struct ToxicChain<'a, W> {
+    toxics: &'a mut [ActiveToxic],
+    to: &'a mut W,
+    stats: &'a ConnectionStats,
+    stream: Stream,
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send> Link for ToxicChain<'_, W> {
+    async fn forward(&mut self, data: Vec<u8>) -> io::Result<()> {
+        let Some((active, rest)) = self.toxics.split_first_mut() else {
+            self.to.write_all(&data).await?;
+            self.stats.forwarded(self.stream, data.len());
+            // TLS streams hold on to what doesn't fill a record until flushed
+            return self.to.flush().await;
+        };
+
+        let mut next = ToxicChain {
+            toxics: rest,
+            to: &mut *self.to,
+            stats: self.stats,
+            stream: self.stream,
+        };
+        if !active.enabled {
+            return next.forward(data).await;
+        }
+        active.toxic.toxic.forward(data, &mut active.state, &mut next).await
+    }
+}
+
+// What the connections of a running proxy need to know about it
+struct Route {
+    proxy: String,
+    upstream: String,
+    protocol: Protocol,
+    tls: Tls,
+    capture: Option<Capture>,
+}
+
+impl Route {
+    // Wraps half of a connection's socket to record its traffic, if the proxy captures
+    fn tap<T>(&self, half: T, connection: u64, stream: Stream) -> Tap<T> {
+        Tap::new(half, self.capture.clone(), connection, stream)
+    }
+}
 
 pub struct Proxy {
-    pub toxics: Vec<Arc<dyn Toxic>>,
+    pub name: String,
+    pub listen: String,
+    pub upstream: String,
+    pub protocol: Protocol,
+    pub enabled: bool,
+    pub tls: Option<TlsConfig>,
+    pub capture: Option<PathBuf>,
+    pub toxics: Toxics,
+    listener: Option<JoinHandle<()>>,
+    connections: Connections,
 }
 
 impl Proxy {
-    pub fn new() -> Self {
-        Proxy { toxics: Vec::new() }
+    pub fn new(config: ProxyConfig) -> Self {
+        Proxy {
+            name: config.name,
+            listen: config.listen,
+            upstream: config.upstream,
+            protocol: config.protocol,
+            enabled: config.enabled,
+            tls: config.tls,
+            capture: config.capture,
+            toxics: watch::Sender::new(Vec::new()),
+            listener: None,
+            connections: Connections::default(),
+        }
     }
 
-    pub fn add_toxic(&mut self, toxic: Arc<dyn Toxic>) {
-        self.toxics.push(toxic);
+    pub fn config(&self) -> ProxyConfig {
+        ProxyConfig {
+            name: self.name.clone(),
+            listen: self.listen.clone(),
+            upstream: self.upstream.clone(),
+            protocol: self.protocol,
+            enabled: self.enabled,
+            tls: self.tls.clone(),
+            capture: self.capture.clone(),
+        }
     }
 
-    fn handle_connection(
-        upstream: TcpStream,
-        downstream_addr: String,
-        toxics: Arc<Vec<Arc<dyn Toxic>>>,
-    ) -> io::Result<()> {
-        let downstream = TcpStream::connect(downstream_addr)?;
-        let upstream_clone = upstream.try_clone()?;
-        let downstream_clone = downstream.try_clone()?;
+    // Checks that a toxic configuration is valid, and that its toxic works on this proxy
+    pub fn check_toxic(&self, config: &ToxicConfig) -> Result<(), String> {
+        config.validate()?;
+        let toxic = toxics::new_toxic(config.kind.clone());
+        if !toxic.supports(self.protocol) {
+            return Err(format!(
+                "{} toxics are not supported on {} proxies",
+                toxic.get_type(),
+                self.protocol
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn toxic(&self, name: &str) -> Option<Arc<ToxicWrapper>> {
+        self.toxics
+            .borrow()
+            .iter()
+            .find(|toxic| toxic.name == name)
+            .cloned()
+    }
 
-        // Handle upstream -> downstream
-        let toxics_clone = Arc::clone(&toxics);
-        thread::spawn(move || {
-            Self::proxy_data(upstream, downstream, toxics_clone, true);
+    // Adds a toxic unless one with the same name exists. Returns the toxic if it was added.
+    pub fn add_toxic(&self, config: ToxicConfig) -> Option<Arc<ToxicWrapper>> {
+        let toxic = Arc::new(ToxicWrapper::new(config));
+        let added = self.toxics.send_if_modified(|toxics| {
+            if toxics.iter().any(|existing| existing.name == toxic.name) {
+                return false;
+            }
+            toxics.push(Arc::clone(&toxic));
+            true
         });
+        if added {
+            events::publish(&self.name, ProxyEvent::ToxicAdded { toxic: toxic.config() });
+        }
+        added.then_some(toxic)
+    }
 
-        // Handle downstream -> upstream
-        let toxics_clone = Arc::clone(&toxics);
-        thread::spawn(move || {
-            Self::proxy_data(downstream_clone, upstream_clone, toxics_clone, false);
+    // Replaces the named toxic in place. Open connections switch over between two chunks of
+    // data, so no chunk sees a mix of the old and new toxic. Returns the toxic if it was found.
+    pub fn update_toxic(&self, name: &str, config: ToxicConfig) -> Option<Arc<ToxicWrapper>> {
+        let toxic = Arc::new(ToxicWrapper::new(ToxicConfig {
+            name: name.to_string(),
+            ..config
+        }));
+        let updated = self.toxics.send_if_modified(|toxics| {
+            match toxics.iter_mut().find(|existing| existing.name == name) {
+                Some(existing) => {
+                    *existing = Arc::clone(&toxic);
+                    true
+                }
+                None => false,
+            }
+        });
+        if updated {
+            events::publish(&self.name, ProxyEvent::ToxicUpdated { toxic: toxic.config() });
+        }
+        updated.then_some(toxic)
+    }
+
+    // Removes every toxic, from open connections too
+    pub fn clear_toxics(&self) {
+        let mut removed = Vec::new();
+        self.toxics.send_if_modified(|toxics| {
+            removed = std::mem::take(toxics);
+            !removed.is_empty()
         });
+        for toxic in removed {
+            let removed = ProxyEvent::ToxicRemoved {
+                toxic: toxic.name.clone(),
+            };
+            events::publish(&self.name, removed);
+        }
+    }
+
+    // Removes the named toxic, from open connections too. Returns whether it was found.
+    pub fn remove_toxic(&self, name: &str) -> bool {
+        let removed = self.toxics.send_if_modified(|toxics| {
+            let count = toxics.len();
+            toxics.retain(|toxic| toxic.name != name);
+            toxics.len() != count
+        });
+        if removed {
+            let toxic = name.to_string();
+            events::publish(&self.name, ProxyEvent::ToxicRemoved { toxic });
+        }
+        removed
+    }
+
+    // Handles a connection from start to end. `accepted` numbers it within its listener, to seed
+    // its toxics, while captures record it by its id like everything else.
+    async fn handle_connection(
+        route: Arc<Route>,
+        client: NetStream,
+        accepted: u64,
+        stats: Arc<ConnectionStats>,
+        toxics: ToxicsReceiver,
+    ) -> io::Result<()> {
+        let proxy = &route.proxy;
+        let _active = ActiveConnection::new(proxy);
+        let id = stats.id;
+        if let Some(capture) = &route.capture {
+            capture.record(id, Event::Open { client: &stats.client });
+        }
+        let mut client = route.tls.accept(client).await?;
+        let upstream = NetStream::connect(&route.upstream).await?;
+        let mut upstream = route.tls.connect(upstream).await?;
+
+        // Toxics see the plaintext, as TLS is terminated and originated around them. Captures
+        // record what is read before the toxics and what is written after them.
+        let (client_read, client_write) = tokio::io::split(&mut client);
+        let (upstream_read, upstream_write) = tokio::io::split(&mut upstream);
+        let client_read = route.tap(client_read, id, Stream::Upstream);
+        let upstream_write = route.tap(upstream_write, id, Stream::Upstream);
+        let upstream_read = route.tap(upstream_read, id, Stream::Downstream);
+        let client_write = route.tap(client_write, id, Stream::Downstream);
+
+        // Handle client -> upstream and upstream -> client concurrently. A clean EOF only closes
+        // its own direction, while an error or a toxic closing the connection drops both.
+        let result = if route.protocol == Protocol::Http {
+            let (requests, responses) = http::sources(client_read, upstream_read);
+            tokio::try_join!(
+                Self::proxy_data(
+                    proxy,
+                    requests,
+                    upstream_write,
+                    accepted,
+                    &stats,
+                    toxics.clone(),
+                    Stream::Upstream,
+                ),
+                Self::proxy_data(
+                    proxy,
+                    responses,
+                    client_write,
+                    accepted,
+                    &stats,
+                    toxics,
+                    Stream::Downstream,
+                ),
+            )
+        } else {
+            let (requests, responses) = (RawSource::new(client_read), RawSource::new(upstream_read));
+            tokio::try_join!(
+                Self::proxy_data(
+                    proxy,
+                    requests,
+                    upstream_write,
+                    accepted,
+                    &stats,
+                    toxics.clone(),
+                    Stream::Upstream,
+                ),
+                Self::proxy_data(
+                    proxy,
+                    responses,
+                    client_write,
+                    accepted,
+                    &stats,
+                    toxics,
+                    Stream::Downstream,
+                ),
+            )
+        };
+
+        if let Err(e) = result {
+            if e.kind() == io::ErrorKind::ConnectionReset {
+                // Abort both sockets so the peers see an RST rather than a FIN
+                client.set_zero_linger()?;
+                upstream.set_zero_linger()?;
+            }
+        }
 
         Ok(())
     }
 
-    fn proxy_data(
-        mut from: TcpStream,
-        mut to: TcpStream,
-        toxics: Arc<Vec<Arc<dyn Toxic>>>,
-        is_upstream: bool,
-    ) {
-        let mut buffer = vec![0; 4096];
+    async fn proxy_data<S, W>(
+        proxy: &str,
+        mut from: S,
+        mut to: W,
+        accepted: u64,
+        stats: &ConnectionStats,
+        mut toxics: ToxicsReceiver,
+        stream: Stream,
+    ) -> io::Result<()>
+    where
+        S: Source,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let direction = stream.to_string();
+        let latency = metrics::LATENCY_HISTOGRAM.with_label_values(&[proxy, &direction]);
+
+        let mut active =
+            Self::refresh_toxics(Vec::new(), &toxics.borrow_and_update(), accepted, stream);
+        stats.set_toxics(stream, &active);
+        let mut closed_by_toxic = Box::pin(Self::until_close(&active));
+
+        for toxic in Self::enabled(&active) {
+            toxic.on_connect().await;
+        }
+
         loop {
-            match from.read(&mut buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    let mut data = buffer[..n].to_vec();
-
-                    // Apply toxics
-                    for toxic in toxics.iter() {
-                        if is_upstream {
-                            toxic.modify_upstream(&mut data);
-                        } else {
-                            toxic.modify_downstream(&mut data);
+            let data = tokio::select! {
+                read = from.read() => read?,
+                error = &mut closed_by_toxic => return Err(error),
+                Ok(()) = toxics.changed() => {
+                    // Pick up toxics added or removed through the API while the connection is open
+                    active = Self::refresh_toxics(
+                        active,
+                        &toxics.borrow_and_update(),
+                        accepted,
+                        stream,
+                    );
+                    stats.set_toxics(stream, &active);
+                    closed_by_toxic = Box::pin(Self::until_close(&active));
+                    continue;
+                }
+            };
+            let Some(data) = data else {
+                break; // EOF
+            };
+
+            let start = Instant::now();
+
+            // Apply toxics
+            Self::record_activations(proxy, &direction, &active);
+            let enabled: Vec<&dyn Toxic> = Self::enabled(&active).collect();
+            let mut data = from.process(data, &enabled).await?;
+            if data.is_empty() {
+                continue; // A toxic answered the request itself
+            }
+            for active in active.iter_mut().filter(|active| active.enabled) {
+                active.toxic.toxic.modify(&mut data, &mut active.state).await;
+            }
+
+            let mut chain = ToxicChain {
+                toxics: &mut active,
+                to: &mut to,
+                stats,
+                stream,
+            };
+            chain.forward(data).await?;
+            latency.observe(start.elapsed().as_secs_f64());
+        }
+
+        Self::record_activations(proxy, &direction, &active);
+        for toxic in Self::enabled(&active) {
+            toxic.on_close().await;
+        }
+
+        // Pass the EOF on to the other side
+        to.shutdown().await
+    }
+
+    // Lines up the toxics on a connection with the proxy's current toxics for `stream`, matching
+    // them by name. Toxics that are still there keep their state, and their toxicity roll unless
+    // they were updated. New ones are rolled afresh, with state seeded for the `accepted`th
+    // connection of the proxy.
+    fn refresh_toxics(
+        mut previous: Vec<ActiveToxic>,
+        toxics: &[Arc<ToxicWrapper>],
+        accepted: u64,
+        stream: Stream,
+    ) -> Vec<ActiveToxic> {
+        toxics
+            .iter()
+            .filter(|toxic| toxic.stream == stream)
+            .map(|toxic| {
+                match previous.iter().position(|active| active.toxic.name == toxic.name) {
+                    Some(i) => {
+                        let mut active = previous.swap_remove(i);
+                        if !Arc::ptr_eq(&active.toxic, toxic) {
+                            active.enabled = toxic.roll(&mut active.state.rng);
+                            active.toxic = Arc::clone(toxic);
+                            // A delay drawn for the connection is drawn again from the new settings,
+                            // and timers start over
+                            active.state.delay = None;
+                            active.state.since = Instant::now();
                         }
+                        active
                     }
-
-                    if let Err(_) = to.write_all(&data) {
-                        break;
+                    None => {
+                        let mut state = toxic.state(accepted);
+                        ActiveToxic {
+                            toxic: Arc::clone(toxic),
+                            enabled: toxic.roll(&mut state.rng),
+                            state,
+                        }
                     }
                 }
-                Err(_) => break,
+            })
+            .collect()
+    }
+
+    fn enabled(active: &[ActiveToxic]) -> impl Iterator<Item = &dyn Toxic> {
+        active
+            .iter()
+            .filter(|active| active.enabled)
+            .map(|active| active.toxic.toxic.as_ref())
+    }
+
+    // Counts every enabled toxic as activated, once per chunk of data and once on close
+    fn record_activations(proxy: &str, direction: &str, active: &[ActiveToxic]) {
+        for toxic in Self::enabled(active) {
+            metrics::TOXIC_ACTIVATIONS
+                .with_label_values(&[proxy, &toxic.get_type(), direction])
+                .inc();
+        }
+    }
+
+    // Resolves as soon as any of the enabled toxics wants the connection closed. Each toxic counts
+    // from when it became active, so building this again keeps the deadlines of toxics that
+    // didn't change.
+    fn until_close(active: &[ActiveToxic]) -> impl Future<Output = io::Error> {
+        let toxics: Vec<(Arc<ToxicWrapper>, Instant)> = active
+            .iter()
+            .filter(|active| active.enabled)
+            .map(|active| (Arc::clone(&active.toxic), active.state.since))
+            .collect();
+        async move {
+            if toxics.is_empty() {
+                return future::pending().await;
             }
+            let closes = toxics.iter().map(|(wrapper, since)| wrapper.toxic.until_close(*since));
+            future::select_all(closes).await.0
         }
     }
 
-    pub fn start(&self, listen_addr: &str, upstream_addr: &str) -> io::Result<()> {
-        let listener = TcpListener::bind(listen_addr)?;
-        let toxics = Arc::new(self.toxics.clone());
+    // Binds the listener and accepts connections on a background task. The bound
+    // address replaces `listen`, so a port of 0 resolves to the one actually assigned.
+    pub async fn start(&mut self) -> io::Result<()> {
+        if self.listener.is_some() || !self.enabled {
+            return Ok(());
+        }
+        if self.protocol == Protocol::Udp && self.tls.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "TLS is not supported on udp proxies",
+            ));
+        }
+        let unix = net::unix_path(&self.listen).or(net::unix_path(&self.upstream));
+        if self.protocol == Protocol::Udp && unix.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unix sockets are not supported on udp proxies",
+            ));
+        }
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let upstream_addr = upstream_addr.to_string();
-                    let toxics = Arc::clone(&toxics);
-                    thread::spawn(move || {
-                        if let Err(e) = Self::handle_connection(stream, upstream_addr, toxics) {
-                            eprintln!("Connection error: {}", e);
-                        }
-                    });
+        let tls = Tls::new(self.tls.as_ref(), &self.upstream)?;
+        let capture = match &self.capture {
+            Some(path) => Some(Capture::open(&self.name, path).await?),
+            None => None,
+        };
+        let route = Arc::new(Route {
+            proxy: self.name.clone(),
+            upstream: self.upstream.clone(),
+            protocol: self.protocol,
+            tls,
+            capture,
+        });
+        if self.protocol == Protocol::Udp {
+            return self.start_udp(route).await;
+        }
+
+        let listener = Listener::bind(&self.listen).await?;
+        self.listen = listener.local_addr()?;
+
+        let toxics = self.toxics.subscribe();
+        let connections = Arc::clone(&self.connections);
+
+        self.listener = Some(tokio::spawn(async move {
+            // Connections are numbered in the order they are accepted, to seed their toxics
+            let mut accepted = 0;
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let connection = accepted;
+                        accepted += 1;
+                        let route = Arc::clone(&route);
+                        let toxics = toxics.clone();
+                        let stats = Arc::new(ConnectionStats::new(&route.proxy, peer));
+                        spawn_connection(&connections, Arc::clone(&stats), async move {
+                            let handled =
+                                Self::handle_connection(route, stream, connection, stats, toxics);
+                            if let Err(e) = handled.await {
+                                eprintln!("Connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => eprintln!("Connection failed: {}", e),
                 }
-                Err(e) => eprintln!("Connection failed: {}", e),
             }
-        }
+        }));
+        Ok(())
+    }
 
+    async fn start_udp(&mut self, route: Arc<Route>) -> io::Result<()> {
+        let socket = Arc::new(UdpSocket::bind(&self.listen).await?);
+        self.listen = socket.local_addr()?.to_string();
+
+        self.listener = Some(tokio::spawn(udp::serve(
+            route,
+            socket,
+            self.toxics.subscribe(),
+            Arc::clone(&self.connections),
+        )));
         Ok(())
     }
+
+    // Closes the listener. TCP connections that are already open run until either side closes,
+    // while UDP sessions end with the listener, as they reply to clients through it.
+    pub async fn stop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+            let _ = listener.await;
+        }
+        if self.protocol == Protocol::Udp {
+            self.close_connections();
+        }
+    }
+
+    // Open connections in the order they were accepted
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|open| open.stats.info())
+            .collect();
+        connections.sort_by_key(|connection| connection.id);
+        connections
+    }
+
+    // Drops one open connection, closing both sides. Returns whether it was found.
+    pub fn close_connection(&self, id: u64) -> bool {
+        match self.connections.lock().unwrap().remove(&id) {
+            Some(open) => {
+                open.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Drops every open connection, closing both sides
+    pub fn close_connections(&self) {
+        for (_, open) in self.connections.lock().unwrap().drain() {
+            open.task.abort();
+        }
+    }
 }
 
-pub type ProxyState = Arc<Mutex<HashMap<String, Vec<Arc<dyn Toxic>>>>>;
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        if let Some(listener) = &self.listener {
+            listener.abort();
+        }
+    }
+}
+
+pub type ProxyState = Arc<tokio::sync::Mutex<HashMap<String, Proxy>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpResponse;
+    use crate::tls::Socket;
+    use crate::toxic::{Jitter, ToxicKind};
+    use std::time::{Duration, Instant};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time;
+
+    async fn start_echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        address
+    }
+
+    async fn start_proxy(upstream: &str) -> Proxy {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "test".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: upstream.to_string(),
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy
+    }
+
+    fn latency(latency_ms: u64) -> ToxicKind {
+        ToxicKind::Latency {
+            latency_ms,
+            jitter: Jitter::NONE,
+        }
+    }
+
+    fn toxic(stream: Stream, kind: ToxicKind) -> ToxicConfig {
+        ToxicConfig {
+            name: String::new(),
+            stream,
+            toxicity: 1.0,
+            seed: None,
+            kind,
+        }
+    }
+
+    async fn round_trip(stream: &mut TcpStream, data: &[u8]) -> Vec<u8> {
+        stream.write_all(data).await.unwrap();
+        let mut received = vec![0; data.len()];
+        stream.read_exact(&mut received).await.unwrap();
+        received
+    }
+
+    #[tokio::test]
+    async fn proxy_forwards_data_to_upstream() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"hello").await, b"hello");
+    }
+
+    #[tokio::test]
+    async fn proxy_start_resolves_listen_port() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        assert!(!proxy.listen.ends_with(":0"));
+    }
+
+    #[tokio::test]
+    async fn proxy_stop_closes_listener() {
+        let upstream = start_echo_server().await;
+        let mut proxy = start_proxy(&upstream).await;
+        let listen = proxy.listen.clone();
+
+        proxy.stop().await;
+
+        assert!(TcpStream::connect(&listen).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn proxy_start_does_not_listen_when_disabled() {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "test".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: "127.0.0.1:8476".to_string(),
+            protocol: Protocol::Tcp,
+            enabled: false,
+            tls: None,
+            capture: None,
+        });
+
+        proxy.start().await.unwrap();
+
+        assert!(proxy.listener.is_none());
+    }
+
+    #[tokio::test]
+    async fn proxy_close_connections_drops_open_connections() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        assert_eq!(proxy.connections.lock().unwrap().len(), 1);
+
+        proxy.close_connections();
+
+        let mut buffer = [0; 16];
+        assert_eq!(client.read(&mut buffer).await.unwrap_or(0), 0);
+        assert!(proxy.connections.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn proxy_lists_connections_with_bytes_and_toxics() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, latency(0)));
+        proxy.add_toxic(ToxicConfig {
+            toxicity: 0.0,
+            ..toxic(Stream::Upstream, latency(0))
+        });
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        client.write_all(b"!").await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+
+        let connections = proxy.connections();
+
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].client, client.local_addr().unwrap().to_string());
+        assert_eq!(connections[0].bytes_upstream, 5);
+        assert_eq!(connections[0].bytes_downstream, 5);
+        assert_eq!(connections[0].toxics, ["latency_downstream"]);
+        assert!(connections[0].started_us > 0);
+    }
+
+    fn socket_address(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        format!("unix:{}", path.display())
+    }
+
+    #[tokio::test]
+    async fn proxy_listens_on_unix_socket_with_toxics() {
+        let upstream = start_echo_server().await;
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "test".to_string(),
+            listen: socket_address("proxy-listen"),
+            upstream,
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy.add_toxic(toxic(Stream::Downstream, latency(100)));
+
+        let path = net::unix_path(&proxy.listen).unwrap();
+        let mut client = tokio::net::UnixStream::connect(path).await.unwrap();
+        let start = Instant::now();
+        client.write_all(b"ping").await.unwrap();
+        let mut received = [0; 4];
+        client.read_exact(&mut received).await.unwrap();
 
+        assert_eq!(&received, b"ping");
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(proxy.connections()[0].client, "unix:");
+    }
+
+    #[tokio::test]
+    async fn proxy_forwards_to_unix_socket_upstream_with_toxics() {
+        let upstream = socket_address("proxy-upstream");
+        let listener = Listener::bind(&upstream).await.unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buffer = [0; 64];
+                    while let Ok(n @ 1..) = stream.read(&mut buffer).await {
+                        let _ = stream.write_all(&buffer[..n]).await;
+                    }
+                });
+            }
+        });
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::LimitData { bytes: 4 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"pingpong").await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+
+        assert_eq!(received, b"ping");
+    }
+
+    #[tokio::test]
+    async fn proxy_rejects_unix_socket_on_udp_proxy() {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "test".to_string(),
+            listen: socket_address("proxy-udp"),
+            upstream: "127.0.0.1:8476".to_string(),
+            protocol: Protocol::Udp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+
+        let error = proxy.start().await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn proxy_close_connection_drops_only_that_connection() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        let mut first = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut first, b"ping").await, b"ping");
+        let mut second = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut second, b"ping").await, b"ping");
+        let id = proxy.connections()[0].id;
+
+        assert!(proxy.close_connection(id));
+        assert!(!proxy.close_connection(id));
+
+        let mut buffer = [0; 16];
+        assert_eq!(first.read(&mut buffer).await.unwrap_or(0), 0);
+        assert_eq!(round_trip(&mut second, b"pong").await, b"pong");
+        assert_eq!(proxy.connections().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn proxy_publishes_connection_and_activation_events() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        let mut events = events::subscribe();
+        proxy.add_toxic(toxic(Stream::Downstream, latency(0)));
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let client_address = client.local_addr().unwrap().to_string();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        drop(client);
+
+        // Other tests publish too, so follow the connection of this client
+        let mut connection = None;
+        let mut seen = Vec::new();
+        while !seen.contains(&"closed") {
+            let record = time::timeout(Duration::from_secs(1), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match record.event {
+                ProxyEvent::ConnectionOpened { connection: id, client }
+                    if client == client_address =>
+                {
+                    connection = Some(id);
+                    seen.push("opened");
+                }
+                ProxyEvent::ToxicActivated { connection: id, ref toxic, stream }
+                    if Some(id) == connection =>
+                {
+                    assert_eq!(toxic, "latency_downstream");
+                    assert_eq!(stream, Stream::Downstream);
+                    seen.push("activated");
+                }
+                ProxyEvent::ConnectionClosed { connection: id } if Some(id) == connection => {
+                    seen.push("closed");
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(seen, ["opened", "activated", "closed"]);
+    }
+
+    #[tokio::test]
+    async fn proxy_forgets_connections_once_closed() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+
+        drop(client);
+        time::sleep(Duration::from_millis(100)).await;
+
+        assert!(proxy.connections.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn proxy_applies_toxics_added_to_live_connections() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, &[0]).await, [0]);
+
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Corrupt { probability: 1.0 }));
+        let mut corrupted = false;
+        for _ in 0..20 {
+            corrupted |= round_trip(&mut client, &[0]).await != [0];
+        }
+        assert!(corrupted);
+    }
+
+    #[tokio::test]
+    async fn proxy_captures_data_before_and_after_toxics() {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let path = std::env::temp_dir().join(format!("proxy-capture-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let upstream = start_echo_server().await;
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "captured".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream,
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: Some(path.clone()),
+        });
+        proxy.start().await.unwrap();
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Corrupt { probability: 1.0 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let echoed = round_trip(&mut client, b"ping").await;
+        assert_ne!(echoed, b"ping");
+
+        // Open, then data received and sent in both directions
+        let mut records = Vec::new();
+        for _ in 0..100 {
+            records = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect();
+            if records.len() >= 5 {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        let find = |event: &str, stream: &str| {
+            records
+                .iter()
+                .find(|record| record["event"] == event && record["stream"] == stream)
+                .map(|record| record["data"].clone())
+                .unwrap()
+        };
+        assert_eq!(records[0]["event"], "open");
+        assert_eq!(find("received", "upstream"), STANDARD.encode("ping"));
+        assert_eq!(find("sent", "upstream"), STANDARD.encode("ping"));
+        assert_eq!(find("received", "downstream"), STANDARD.encode("ping"));
+        assert_eq!(find("sent", "downstream"), STANDARD.encode(&echoed));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn proxy_capture_keeps_connection_ids_unique_across_restarts() {
+        let path = std::env::temp_dir().join(format!("proxy-restart-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let upstream = start_echo_server().await;
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "restarted".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream,
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: Some(path.clone()),
+        });
+        proxy.start().await.unwrap();
+        let mut first = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut first, b"ping").await, b"ping");
+        let first_id = proxy.connections()[0].id;
+
+        proxy.stop().await;
+        proxy.start().await.unwrap();
+        let mut second = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut second, b"ping").await, b"ping");
+        let second_id = proxy.connections().last().unwrap().id;
+
+        let mut opened = Vec::new();
+        for _ in 0..100 {
+            opened = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .filter(|record| record["event"] == "open")
+                .map(|record| record["connection"].as_u64().unwrap())
+                .collect();
+            if opened.len() >= 2 {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_ne!(first_id, second_id);
+        assert_eq!(opened, [first_id, second_id]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn proxy_lists_publishes_and_captures_connections_by_the_same_id() {
+        let path = std::env::temp_dir().join(format!("proxy-ids-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let upstream = start_echo_server().await;
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "identified".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream,
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: Some(path.clone()),
+        });
+        proxy.start().await.unwrap();
+        let mut events = events::subscribe();
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let client_address = client.local_addr().unwrap().to_string();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        let listed = proxy.connections()[0].id;
+
+        // Other tests publish too, so follow the connection of this client
+        let published = loop {
+            let record = time::timeout(Duration::from_secs(1), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match record.event {
+                ProxyEvent::ConnectionOpened { connection, client } if client == client_address => {
+                    break connection
+                }
+                _ => {}
+            }
+        };
+
+        let mut captured = None;
+        for _ in 0..100 {
+            captured = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .find(|record| record["event"] == "open")
+                .and_then(|record| record["connection"].as_u64());
+            if captured.is_some() {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(published, listed);
+        assert_eq!(captured, Some(listed));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn proxy_delays_close_with_slow_close_toxic() {
+        // Upstream that closes every connection straight away
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::SlowClose { delay_ms: 200 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        let mut buffer = [0; 16];
+        assert_eq!(client.read(&mut buffer).await.unwrap(), 0);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn proxy_throttles_with_bandwidth_toxic() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Bandwidth { rate: 20 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        assert_eq!(round_trip(&mut client, &[7; 4000]).await, vec![7; 4000]);
+        // 4000 bytes at 20 KB/s take at least 200ms to get through in each direction
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn proxy_closes_connection_with_timeout_toxic() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 200 }));
+        let start = Instant::now();
+        client.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 16];
+        // Nothing comes back, and the connection is closed once the timeout passes
+        assert_eq!(client.read(&mut buffer).await.unwrap_or(0), 0);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn proxy_keeps_timeout_deadline_while_toxics_change() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 300 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        let closed = async {
+            let mut buffer = [0; 16];
+            assert_eq!(client.read(&mut buffer).await.unwrap_or(0), 0);
+            start.elapsed()
+        };
+        // Other toxics come and go while the timeout is pending
+        let changes = async {
+            for i in 0..8 {
+                time::sleep(Duration::from_millis(100)).await;
+                let name = format!("lag_{}", i);
+                proxy.add_toxic(ToxicConfig { name, ..toxic(Stream::Upstream, latency(0)) });
+            }
+        };
+        let (elapsed, ()) = tokio::join!(closed, changes);
+
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_millis(600));
+    }
+
+    #[tokio::test]
+    async fn proxy_counts_only_bytes_toxics_pass_on() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 0 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+
+        // The echo is swallowed by the timeout toxic on its way back
+        let connections = proxy.connections();
+        assert_eq!(connections[0].bytes_upstream, 4);
+        assert_eq!(connections[0].bytes_downstream, 0);
+    }
+
+    #[tokio::test]
+    async fn proxy_holds_connection_with_zero_timeout_toxic() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 0 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 16];
+        let read = time::timeout(Duration::from_millis(300), client.read(&mut buffer)).await;
+        assert!(read.is_err());
+    }
+
+    #[tokio::test]
+    async fn proxy_delivers_sliced_data_intact() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(
+            Stream::Downstream,
+            ToxicKind::Slicer {
+                average_size: 8,
+                size_variation: 4,
+                delay_us: 100,
+            },
+        ));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(round_trip(&mut client, &data).await, data);
+    }
+
+    #[tokio::test]
+    async fn proxy_truncates_response_with_limit_data_toxic() {
+        // Upstream that greets every connection with 100 bytes
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(&[1; 100]).await;
+                let mut buffer = [0; 16];
+                let _ = stream.read(&mut buffer).await;
+            }
+        });
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::LimitData { bytes: 10 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, vec![1; 10]);
+    }
+
+    #[tokio::test]
+    async fn proxy_resets_connection_with_reset_peer_toxic() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::ResetPeer { timeout_ms: 50 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 16];
+        let error = client.read(&mut buffer).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[tokio::test]
+    async fn proxy_resets_idle_connection_with_reset_peer_toxic() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::ResetPeer { timeout_ms: 50 }));
+
+        // The client never sends anything, and is reset all the same
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        let mut buffer = [0; 16];
+        let read = time::timeout(Duration::from_secs(2), client.read(&mut buffer)).await;
+        assert_eq!(read.unwrap().unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn proxy_resets_on_time_while_other_toxics_change() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::ResetPeer { timeout_ms: 300 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        let reset = async {
+            let mut buffer = [0; 16];
+            let error = client.read(&mut buffer).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+            start.elapsed()
+        };
+        // An unrelated toxic is added, updated and removed while the reset is pending
+        let changes = async {
+            let lag = ToxicConfig {
+                name: "lag".to_string(),
+                ..toxic(Stream::Upstream, latency(0))
+            };
+            time::sleep(Duration::from_millis(100)).await;
+            proxy.add_toxic(lag.clone());
+            time::sleep(Duration::from_millis(100)).await;
+            proxy.update_toxic("lag", ToxicConfig { kind: latency(5), ..lag });
+            time::sleep(Duration::from_millis(100)).await;
+            proxy.remove_toxic("lag");
+        };
+        let (elapsed, ()) = tokio::join!(reset, changes);
+
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_millis(600));
+    }
+
+    #[tokio::test]
+    async fn proxy_applies_toxics_only_to_their_stream() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Upstream, latency(200)));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        // Only the request is delayed, the echo comes straight back
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(200));
+        assert!(elapsed < Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn proxy_skips_toxics_with_zero_toxicity() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(ToxicConfig {
+            toxicity: 0.0,
+            seed: None,
+            ..toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 0 })
+        });
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+    }
+
+    #[tokio::test]
+    async fn proxy_rejects_duplicate_toxic_names() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        let lag = |stream| toxic(stream, latency(10));
+        assert!(proxy.add_toxic(lag(Stream::Downstream)).is_some());
+        assert!(proxy.add_toxic(lag(Stream::Downstream)).is_none());
+        // The same type on the other stream gets a different default name
+        assert!(proxy.add_toxic(lag(Stream::Upstream)).is_some());
+        assert_eq!(proxy.toxics.borrow().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn proxy_applies_toxic_updates_to_live_connections() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, latency(300)));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let updated = proxy.update_toxic(
+            "latency_downstream",
+            toxic(Stream::Downstream, latency(0)),
+        );
+        assert!(updated.is_some());
+        let start = Instant::now();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn proxy_removes_toxics_from_live_connections() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, ToxicKind::Timeout { timeout_ms: 0 }));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"lost").await.unwrap();
+        let mut buffer = [0; 16];
+        let read = time::timeout(Duration::from_millis(200), client.read(&mut buffer)).await;
+        assert!(read.is_err());
+
+        assert!(proxy.remove_toxic("timeout_downstream"));
+        assert!(!proxy.remove_toxic("timeout_downstream"));
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+    }
+
+    #[tokio::test]
+    async fn proxy_records_metrics_per_direction() {
+        let upstream = start_echo_server().await;
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "metrics".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream,
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy.add_toxic(toxic(Stream::Upstream, latency(10)));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        assert_eq!(round_trip(&mut client, b"ping").await, b"ping");
+
+        let bytes = |direction| {
+            metrics::BYTES_TRANSFERRED
+                .with_label_values(&["metrics", direction])
+                .get()
+        };
+        let activations = |direction| {
+            metrics::TOXIC_ACTIVATIONS
+                .with_label_values(&["metrics", "latency", direction])
+                .get()
+        };
+        assert_eq!(bytes("upstream"), 4);
+        assert_eq!(bytes("downstream"), 4);
+        assert_eq!(activations("upstream"), 1);
+        assert_eq!(activations("downstream"), 0);
+        assert_eq!(
+            metrics::ACTIVE_CONNECTIONS.with_label_values(&["metrics"]).get(),
+            1
+        );
+
+        drop(client);
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            metrics::ACTIVE_CONNECTIONS.with_label_values(&["metrics"]).get(),
+            0
+        );
+    }
+
+    // Answers every request with its own head as the body
+    async fn start_http_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    while stream.read_buf(&mut buffer).await.unwrap_or(0) > 0 {
+                        while let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                            let head: Vec<u8> = buffer.drain(..end + 4).collect();
+                            let mut response =
+                                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", head.len())
+                                    .into_bytes();
+                            response.extend_from_slice(&head);
+                            let _ = stream.write_all(&response).await;
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    async fn http_exchange(stream: &mut TcpStream, request: &str) -> HttpResponse {
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut buffer = Vec::new();
+        loop {
+            if let Some((response, _)) = HttpResponse::parse(&buffer, false, false).unwrap() {
+                return response;
+            }
+            assert!(stream.read_buf(&mut buffer).await.unwrap() > 0);
+        }
+    }
+
+    async fn start_http_proxy(upstream: &str) -> Proxy {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "http".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: upstream.to_string(),
+            protocol: Protocol::Http,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy
+    }
+
+    #[tokio::test]
+    async fn http_proxy_injects_status_on_matching_paths() {
+        let upstream = start_http_server().await;
+        let proxy = start_http_proxy(&upstream).await;
+        let kind = ToxicKind::HttpStatus {
+            status: 503,
+            path_prefix: "/api".to_string(),
+            body: String::new(),
+        };
+        proxy.add_toxic(toxic(Stream::Upstream, kind));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let injected = http_exchange(&mut client, "GET /api/orders HTTP/1.1\r\n\r\n").await;
+        let forwarded = http_exchange(&mut client, "GET /health HTTP/1.1\r\n\r\n").await;
+
+        assert_eq!(injected.status, 503);
+        assert_eq!(forwarded.status, 200);
+        assert_eq!(forwarded.body, b"GET /health HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn http_proxy_counts_only_forwarded_requests() {
+        let upstream = start_http_server().await;
+        let proxy = start_http_proxy(&upstream).await;
+        let kind = ToxicKind::HttpStatus {
+            status: 503,
+            path_prefix: String::new(),
+            body: String::new(),
+        };
+        proxy.add_toxic(toxic(Stream::Upstream, kind));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let injected = http_exchange(&mut client, "GET / HTTP/1.1\r\n\r\n").await;
+        assert_eq!(injected.status, 503);
+
+        let connections = proxy.connections();
+        assert_eq!(connections[0].bytes_upstream, 0);
+        assert_eq!(connections[0].bytes_downstream, injected.to_bytes().len() as u64);
+    }
+
+    #[tokio::test]
+    async fn http_proxy_forwards_head_before_body() {
+        // Upstream that only asks for the body once it has the head, as for `100-continue`
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = Vec::new();
+            while !buffer.ends_with(b"\r\n\r\n") {
+                assert!(stream.read_buf(&mut buffer).await.unwrap() > 0);
+            }
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await.unwrap();
+            let mut body = [0; 5];
+            stream.read_exact(&mut body).await.unwrap();
+            let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+            stream.write_all(&[&response[..], &body].concat()).await.unwrap();
+        });
+        let proxy = start_http_proxy(&upstream).await;
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let head = "POST / HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n";
+        let interim = time::timeout(Duration::from_secs(2), http_exchange(&mut client, head));
+        assert_eq!(interim.await.unwrap().status, 100);
+        let response = http_exchange(&mut client, "hello").await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn http_proxy_streams_responses_as_they_arrive() {
+        // Upstream that sends the first chunk of an event stream and then holds the response open
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = Vec::new();
+            while !buffer.ends_with(b"\r\n\r\n") {
+                assert!(stream.read_buf(&mut buffer).await.unwrap() > 0);
+            }
+            let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n";
+            stream.write_all(response).await.unwrap();
+            time::sleep(Duration::from_secs(5)).await;
+        });
+        let proxy = start_http_proxy(&upstream).await;
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        client.write_all(b"GET /events HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut received = Vec::new();
+        let first_chunk = async {
+            while !received.ends_with(b"hello\r\n") {
+                assert!(client.read_buf(&mut received).await.unwrap() > 0);
+            }
+        };
+        time::timeout(Duration::from_secs(2), first_chunk).await.unwrap();
+
+        assert!(received.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[tokio::test]
+    async fn http_proxy_strips_request_headers() {
+        let upstream = start_http_server().await;
+        let proxy = start_http_proxy(&upstream).await;
+        let kind = ToxicKind::StripHeaders {
+            headers: vec!["authorization".to_string()],
+            path_prefix: String::new(),
+        };
+        proxy.add_toxic(toxic(Stream::Upstream, kind));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let request = "GET / HTTP/1.1\r\nHost: shop\r\nAuthorization: secret\r\n\r\n";
+        let response = http_exchange(&mut client, request).await;
+
+        assert_eq!(response.body, b"GET / HTTP/1.1\r\nHost: shop\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn http_proxy_applies_stream_toxics_to_whole_messages() {
+        let upstream = start_http_server().await;
+        let proxy = start_http_proxy(&upstream).await;
+        proxy.add_toxic(toxic(Stream::Downstream, latency(100)));
+
+        let mut client = TcpStream::connect(&proxy.listen).await.unwrap();
+        let start = Instant::now();
+        let response = http_exchange(&mut client, "GET / HTTP/1.1\r\n\r\n").await;
+
+        assert_eq!(response.status, 200);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    fn localhost_tls() -> TlsConfig {
+        TlsConfig {
+            cert: Some("testdata/localhost.pem".into()),
+            key: Some("testdata/localhost-key.pem".into()),
+            ..Default::default()
+        }
+    }
+
+    // TLS echo server with the test certificate
+    async fn start_tls_echo_server() -> String {
+        let tls = Tls::new(Some(&localhost_tls()), "").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let tls = Arc::new(tls);
+            while let Ok((stream, _)) = listener.accept().await {
+                let tls = Arc::clone(&tls);
+                tokio::spawn(async move {
+                    let mut stream = tls.accept(stream).await.unwrap();
+                    let (mut reader, mut writer) = tokio::io::split(&mut stream);
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        address
+    }
+
+    // Connects to `address` over TLS, trusting the test certificate
+    async fn connect_tls(address: &str) -> Box<dyn Socket> {
+        let config = TlsConfig {
+            upstream: true,
+            server_name: Some("localhost".to_string()),
+            ca: Some("testdata/localhost.pem".into()),
+            ..Default::default()
+        };
+        let tls = Tls::new(Some(&config), address).unwrap();
+        tls.connect(TcpStream::connect(address).await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn start_tls_proxy(upstream: &str, protocol: Protocol, tls: TlsConfig) -> Proxy {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "tls".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: upstream.to_string(),
+            protocol,
+            enabled: true,
+            tls: Some(tls),
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy
+    }
+
+    #[tokio::test]
+    async fn tls_proxy_terminates_and_reoriginates() {
+        let upstream = start_tls_echo_server().await;
+        let tls = TlsConfig {
+            upstream: true,
+            server_name: Some("localhost".to_string()),
+            ca: Some("testdata/localhost.pem".into()),
+            ..localhost_tls()
+        };
+        let proxy = start_tls_proxy(&upstream, Protocol::Tcp, tls).await;
+        proxy.add_toxic(toxic(
+            Stream::Downstream,
+            ToxicKind::Slicer {
+                average_size: 2,
+                size_variation: 1,
+                delay_us: 10,
+            },
+        ));
+
+        let mut client = connect_tls(&proxy.listen).await;
+        client.write_all(b"hello world").await.unwrap();
+        let mut received = vec![0; 11];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(received, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn tls_proxy_shows_plaintext_to_http_toxics() {
+        let upstream = start_http_server().await;
+        let proxy = start_tls_proxy(&upstream, Protocol::Http, localhost_tls()).await;
+        let kind = ToxicKind::HttpStatus {
+            status: 429,
+            path_prefix: String::new(),
+            body: String::new(),
+        };
+        proxy.add_toxic(toxic(Stream::Upstream, kind));
+
+        let mut client = connect_tls(&proxy.listen).await;
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut buffer = Vec::new();
+        while HttpResponse::parse(&buffer, false, false).unwrap().is_none() {
+            assert!(client.read_buf(&mut buffer).await.unwrap() > 0);
+        }
+
+        assert!(buffer.starts_with(b"HTTP/1.1 429 Too Many Requests\r\n"));
+    }
+
+    #[tokio::test]
+    async fn tls_proxy_without_upstream_tls_forwards_plaintext() {
+        let upstream = start_echo_server().await;
+        let proxy = start_tls_proxy(&upstream, Protocol::Tcp, localhost_tls()).await;
+
+        let mut client = connect_tls(&proxy.listen).await;
+        client.write_all(b"ping").await.unwrap();
+        let mut received = vec![0; 4];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(received, b"ping");
+    }
+
+    #[tokio::test]
+    async fn proxy_start_rejects_tls_on_udp() {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "dns".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: "127.0.0.1:53".to_string(),
+            protocol: Protocol::Udp,
+            enabled: true,
+            tls: Some(localhost_tls()),
+            capture: None,
+        });
+
+        let error = proxy.start().await.unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn proxy_handles_many_concurrent_connections() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+
+        let mut clients = Vec::new();
+        for _ in 0..500 {
+            clients.push(TcpStream::connect(&proxy.listen).await.unwrap());
+        }
+        for client in clients.iter_mut() {
+            assert_eq!(round_trip(client, b"ping").await, b"ping");
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use super::Source;
+use crate::http::{Body, HttpRequest, HttpResponse};
+use crate::toxic::Toxic;
+use async_trait::async_trait;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+
+// What the client gets for a request: the server's response, or one a toxic answered with
+enum Exchange {
+    Forwarded(HttpRequest),
+    Injected(HttpRequest, HttpResponse),
+}
+
+// Sources for both directions of an HTTP connection. Messages are passed on head first with their
+// body streamed after it, so large bodies don't pile up in memory, clients waiting for
+// `100 Continue` get it and long-lived responses such as event streams keep flowing. The request
+// source tells the response source which request each response answers, in order, so pipelined
+// requests keep working.
+pub(super) fn sources<C, U>(client: C, upstream: U) -> (RequestSource<C>, ResponseSource<U>)
+where
+    C: AsyncRead + Unpin + Send,
+    U: AsyncRead + Unpin + Send,
+{
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let requests = RequestSource {
+        reader: client,
+        buffer: Vec::new(),
+        exchanges: sender,
+        body: Body::Done,
+        in_body: false,
+        forward_body: true,
+        raw: false,
+    };
+    let responses = ResponseSource {
+        reader: upstream,
+        buffer: Vec::new(),
+        exchanges: receiver,
+        current: None,
+        body: Body::Done,
+        in_body: false,
+        forward_body: true,
+        raw: false,
+    };
+    (requests, responses)
+}
+
+// Passes on whatever is buffered, then reads straight from the socket
+async fn read_raw<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+) -> io::Result<Option<Vec<u8>>> {
+    if buffer.is_empty() && reader.read_buf(buffer).await? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(std::mem::take(buffer)))
+}
+
+pub(super) struct RequestSource<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    exchanges: mpsc::UnboundedSender<Exchange>,
+    // What is left of the body of the last request
+    body: Body,
+    // Whether the last piece read is part of a body rather than a head
+    in_body: bool,
+    // Whether the body of the last request goes to the server. It doesn't when a toxic answered.
+    forward_body: bool,
+    // Set once the connection is upgraded to another protocol
+    raw: bool,
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> Source for RequestSource<R> {
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if self.raw {
+                return read_raw(&mut self.reader, &mut self.buffer).await;
+            }
+            if !self.body.is_done() {
+                let length = self.body.advance(&self.buffer)?;
+                if length > 0 {
+                    self.in_body = true;
+                    return Ok(Some(self.buffer.drain(..length).collect()));
+                }
+            } else if let Some((request, length)) = HttpRequest::parse_head(&self.buffer)? {
+                self.body = Body::new(&request.headers)?;
+                self.in_body = false;
+                return Ok(Some(self.buffer.drain(..length).collect()));
+            }
+            if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                if self.buffer.is_empty() && self.body.is_done() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete request",
+                ));
+            }
+        }
+    }
+
+    async fn process(&mut self, data: Vec<u8>, toxics: &[&dyn Toxic]) -> io::Result<Vec<u8>> {
+        if self.raw {
+            return Ok(data);
+        }
+        if self.in_body {
+            return Ok(if self.forward_body { data } else { Vec::new() });
+        }
+        let Some((mut request, _)) = HttpRequest::parse_head(&data)? else {
+            unreachable!("read only returns whole heads");
+        };
+
+        for toxic in toxics {
+            if let Some(response) = toxic.request(&mut request).await {
+                // The server never sees the request, the client gets the toxic's response instead
+                let _ = self.exchanges.send(Exchange::Injected(request, response));
+                self.forward_body = false;
+                return Ok(Vec::new());
+            }
+        }
+
+        self.forward_body = true;
+        let data = request.to_bytes();
+        self.raw = request.is_upgrade();
+        let _ = self.exchanges.send(Exchange::Forwarded(request));
+        Ok(data)
+    }
+}
+
+pub(super) struct ResponseSource<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    exchanges: mpsc::UnboundedReceiver<Exchange>,
+    // The exchange the next response belongs to
+    current: Option<Exchange>,
+    // What is left of the body of the last response
+    body: Body,
+    // Whether the last piece read is part of a body rather than a head
+    in_body: bool,
+    // Whether the body of the last response goes to the client. It doesn't when a toxic
+    // replaced the response.
+    forward_body: bool,
+    raw: bool,
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> Source for ResponseSource<R> {
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if self.raw {
+                return read_raw(&mut self.reader, &mut self.buffer).await;
+            }
+
+            if !self.body.is_done() {
+                let length = self.body.advance(&self.buffer)?;
+                if length > 0 {
+                    self.in_body = true;
+                    return Ok(Some(self.buffer.drain(..length).collect()));
+                }
+                if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                    if self.body == Body::UntilEof {
+                        // Responses without a length end when the server closes the connection
+                        self.body = Body::Done;
+                        return Ok(None);
+                    }
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "incomplete response",
+                    ));
+                }
+                continue;
+            }
+
+            let Some(exchange) = &self.current else {
+                match self.exchanges.recv().await {
+                    Some(exchange) => self.current = Some(exchange),
+                    // The client is done sending requests, pass on anything the server still sends
+                    None => self.raw = true,
+                }
+                continue;
+            };
+
+            self.in_body = false;
+            let request = match exchange {
+                Exchange::Injected(_, response) => return Ok(Some(response.to_bytes())),
+                Exchange::Forwarded(request) => request,
+            };
+            if let Some((response, length)) = HttpResponse::parse_head(&self.buffer)? {
+                self.body = Body::of_response(&response, request.method == "HEAD")?;
+                return Ok(Some(self.buffer.drain(..length).collect()));
+            }
+            if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete response",
+                ));
+            }
+        }
+    }
+
+    async fn process(&mut self, data: Vec<u8>, toxics: &[&dyn Toxic]) -> io::Result<Vec<u8>> {
+        if self.raw {
+            return Ok(data);
+        }
+        if self.in_body {
+            return Ok(if self.forward_body { data } else { Vec::new() });
+        }
+        let Some(exchange) = self.current.take() else {
+            return Ok(data);
+        };
+        let (request, mut response) = match &exchange {
+            Exchange::Injected(request, response) => (request, response.clone()),
+            Exchange::Forwarded(request) => {
+                let Some((response, _)) = HttpResponse::parse_head(&data)? else {
+                    unreachable!("read only returns whole heads");
+                };
+                // Interim responses such as 100 Continue come ahead of the final one
+                if response.status < 200 && response.status != 101 {
+                    self.current = Some(exchange);
+                    return Ok(data);
+                }
+                (request, response)
+            }
+        };
+
+        self.forward_body = true;
+        for toxic in toxics {
+            if let Some(replacement) = toxic.response(request, &mut response).await {
+                // The client gets the toxic's response, the server's body goes nowhere
+                response = replacement;
+                self.forward_body = false;
+            }
+        }
+        self.raw = request.is_upgrade();
+        Ok(response.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxics::http_status::HttpStatusToxic;
+    use tokio::io::AsyncWriteExt;
+
+    fn status_toxic() -> HttpStatusToxic {
+        HttpStatusToxic {
+            status: 429,
+            path_prefix: "/limited".to_string(),
+            body: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn request_source_reads_pipelined_requests_one_by_one() {
+        let client: &[u8] = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let (mut requests, _) = sources(client, &b""[..]);
+
+        assert_eq!(
+            requests.read().await.unwrap().unwrap(),
+            b"GET /a HTTP/1.1\r\n\r\n"
+        );
+        assert_eq!(
+            requests.read().await.unwrap().unwrap(),
+            b"GET /b HTTP/1.1\r\n\r\n"
+        );
+        assert_eq!(requests.read().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn request_source_passes_head_on_before_body_arrives() {
+        let (mut client, reader) = tokio::io::duplex(1024);
+        let (mut requests, _) = sources(reader, &b""[..]);
+        let head = b"POST /upload HTTP/1.1\r\nContent-Length: 6\r\nExpect: 100-continue\r\n\r\n";
+        client.write_all(head).await.unwrap();
+
+        let read = requests.read().await.unwrap().unwrap();
+        assert_eq!(requests.process(read, &[]).await.unwrap(), head);
+
+        client.write_all(b"abc").await.unwrap();
+        let read = requests.read().await.unwrap().unwrap();
+        assert_eq!(requests.process(read, &[]).await.unwrap(), b"abc");
+        client.write_all(b"defGET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let read = requests.read().await.unwrap().unwrap();
+        assert_eq!(requests.process(read, &[]).await.unwrap(), b"def");
+        assert_eq!(
+            requests.read().await.unwrap().unwrap(),
+            b"GET / HTTP/1.1\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_source_drops_body_of_answered_request() {
+        let client: &[u8] =
+            b"POST /limited HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcGET /ok HTTP/1.1\r\n\r\n";
+        let (mut requests, _) = sources(client, &b""[..]);
+        let toxic = status_toxic();
+        let toxics: [&dyn Toxic; 1] = [&toxic];
+
+        let mut forwarded = Vec::new();
+        while let Some(read) = requests.read().await.unwrap() {
+            forwarded.extend(requests.process(read, &toxics).await.unwrap());
+        }
+        assert_eq!(forwarded, b"GET /ok HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn response_source_answers_injected_requests_in_order() {
+        let client: &[u8] = b"GET /limited HTTP/1.1\r\n\r\nGET /ok HTTP/1.1\r\n\r\n";
+        let upstream: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let (mut requests, mut responses) = sources(client, upstream);
+        let toxic = status_toxic();
+        let toxics: [&dyn Toxic; 1] = [&toxic];
+
+        let first = requests.read().await.unwrap().unwrap();
+        assert!(requests.process(first, &toxics).await.unwrap().is_empty());
+        let second = requests.read().await.unwrap().unwrap();
+        assert!(!requests.process(second, &toxics).await.unwrap().is_empty());
+
+        let response = responses.read().await.unwrap().unwrap();
+        let response = responses.process(response, &[]).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 429 Too Many Requests\r\n"));
+        let response = responses.read().await.unwrap().unwrap();
+        let response = responses.process(response, &[]).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[tokio::test]
+    async fn response_source_streams_chunked_body() {
+        let client: &[u8] = b"GET /events HTTP/1.1\r\n\r\n";
+        let (mut server, upstream) = tokio::io::duplex(1024);
+        let (mut requests, mut responses) = sources(client, upstream);
+        let request = requests.read().await.unwrap().unwrap();
+        requests.process(request, &[]).await.unwrap();
+        drop(requests);
+
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        server.write_all(head).await.unwrap();
+        let read = responses.read().await.unwrap().unwrap();
+        assert_eq!(responses.process(read, &[]).await.unwrap(), head);
+        server.write_all(b"5\r\nhello\r\n").await.unwrap();
+        let read = responses.read().await.unwrap().unwrap();
+        assert_eq!(responses.process(read, &[]).await.unwrap(), b"5\r\nhello\r\n");
+
+        server.write_all(b"0\r\n\r\n").await.unwrap();
+        drop(server);
+        assert_eq!(responses.read().await.unwrap().unwrap(), b"0\r\n\r\n");
+        assert_eq!(responses.read().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn response_source_drops_body_of_replaced_response() {
+        let client: &[u8] = b"GET /limited HTTP/1.1\r\n\r\nGET /ok HTTP/1.1\r\n\r\n";
+        let upstream: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc\
+            HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let (mut requests, mut responses) = sources(client, upstream);
+        while let Some(request) = requests.read().await.unwrap() {
+            requests.process(request, &[]).await.unwrap();
+        }
+        drop(requests);
+        let toxic = status_toxic();
+        let toxics: [&dyn Toxic; 1] = [&toxic];
+
+        let mut forwarded = Vec::new();
+        while let Some(read) = responses.read().await.unwrap() {
+            forwarded.extend(responses.process(read, &toxics).await.unwrap());
+        }
+        let replaced = b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\n\r\n";
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        assert_eq!(forwarded, [&replaced[..], ok].concat());
+    }
+
+    #[tokio::test]
+    async fn response_source_keeps_request_across_interim_responses() {
+        let client: &[u8] = b"POST /limited HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+        let upstream: &[u8] =
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let (mut requests, mut responses) = sources(client, upstream);
+        let request = requests.read().await.unwrap().unwrap();
+        requests.process(request, &[]).await.unwrap();
+        let toxic = status_toxic();
+        let toxics: [&dyn Toxic; 1] = [&toxic];
+
+        let interim = responses.read().await.unwrap().unwrap();
+        let interim = responses.process(interim, &toxics).await.unwrap();
+        assert!(interim.starts_with(b"HTTP/1.1 100 Continue"));
+        let last = responses.read().await.unwrap().unwrap();
+        let last = responses.process(last, &toxics).await.unwrap();
+        assert!(last.starts_with(b"HTTP/1.1 429"));
+    }
+}
@@ -0,0 +1,440 @@
+use super::{
+    spawn_connection, ActiveToxic, ConnectionStats, Connections, Proxy, Route, ToxicsReceiver,
+};
+use crate::capture::Event;
+use crate::metrics::{self, ActiveConnection};
+use crate::toxic::{Datagram, Stream, ToxicWrapper};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::{self, Instant};
+
+// Sessions without datagrams in either direction for this long are closed
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+// Datagrams waiting for a busy session beyond this many are dropped, as a full socket buffer would
+const SESSION_QUEUE: usize = 256;
+const MAX_DATAGRAM: usize = 65536;
+
+// Receives datagrams on the listening socket and hands them to a session per client address.
+// Each session has its own upstream socket, so replies can be told apart and sent back.
+pub(super) async fn serve(
+    route: Arc<Route>,
+    listener: Arc<UdpSocket>,
+    toxics: ToxicsReceiver,
+    connections: Connections,
+) {
+    let mut sessions: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    // Sessions are numbered in the order they start, to seed their toxics
+    let mut started = 0;
+    let mut buffer = vec![0; MAX_DATAGRAM];
+
+    loop {
+        let (n, client) = match listener.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Datagram receive failed: {}", e);
+                continue;
+            }
+        };
+
+        let mut data = buffer[..n].to_vec();
+        if let Some(session) = sessions.get(&client) {
+            match session.try_send(data) {
+                Ok(()) | Err(TrySendError::Full(_)) => continue,
+                // The session timed out, start a new one for the client
+                Err(TrySendError::Closed(returned)) => data = returned,
+            }
+        }
+
+        sessions.retain(|_, session| !session.is_closed());
+        let (sender, receiver) = mpsc::channel(SESSION_QUEUE);
+        sender.try_send(data).expect("new session queue has room");
+        sessions.insert(client, sender);
+        let accepted = started;
+        started += 1;
+
+        let route = Arc::clone(&route);
+        let listener = Arc::clone(&listener);
+        let toxics = toxics.clone();
+        let stats = Arc::new(ConnectionStats::new(&route.proxy, client.to_string()));
+        spawn_connection(&connections, Arc::clone(&stats), async move {
+            let session = session(route, listener, client, accepted, stats, receiver, toxics);
+            if let Err(e) = session.await {
+                eprintln!("Session error: {}", e);
+            }
+        });
+    }
+}
+
+async fn session(
+    route: Arc<Route>,
+    listener: Arc<UdpSocket>,
+    client: SocketAddr,
+    accepted: u64,
+    stats: Arc<ConnectionStats>,
+    mut datagrams: mpsc::Receiver<Vec<u8>>,
+    mut toxics: ToxicsReceiver,
+) -> io::Result<()> {
+    let _active = ActiveConnection::new(&route.proxy);
+    if let Some(capture) = &route.capture {
+        capture.record(stats.id, Event::Open { client: &stats.client });
+    }
+    let upstream_addr = lookup_host(&route.upstream).await?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no address for {}", route.upstream),
+        )
+    })?;
+    let local: SocketAddr = if upstream_addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(upstream_addr).await?;
+
+    let current = toxics.borrow_and_update().clone();
+    let mut upstream_pipeline =
+        Pipeline::new(&route, accepted, &stats, Stream::Upstream, &current);
+    let mut downstream_pipeline =
+        Pipeline::new(&route, accepted, &stats, Stream::Downstream, &current);
+
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    let idle = time::sleep(SESSION_TIMEOUT);
+    tokio::pin!(idle);
+
+    loop {
+        let due = [upstream_pipeline.next_due(), downstream_pipeline.next_due()]
+            .into_iter()
+            .flatten()
+            .min();
+
+        tokio::select! {
+            received = datagrams.recv() => {
+                // The listener is gone, so replies can't reach the client anymore
+                let Some(data) = received else {
+                    return Ok(());
+                };
+                upstream_pipeline.push(data);
+                idle.as_mut().reset(Instant::now() + SESSION_TIMEOUT);
+            }
+            received = socket.recv(&mut buffer) => {
+                match received {
+                    Ok(n) => {
+                        downstream_pipeline.push(buffer[..n].to_vec());
+                        idle.as_mut().reset(Instant::now() + SESSION_TIMEOUT);
+                    }
+                    // Nothing listens upstream, the datagram is lost as it would be without us
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            _ = time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                let now = Instant::now();
+                for data in upstream_pipeline.take_due(now) {
+                    match socket.send(&data).await {
+                        Err(e) if e.kind() != io::ErrorKind::ConnectionRefused => return Err(e),
+                        _ => {}
+                    }
+                }
+                for data in downstream_pipeline.take_due(now) {
+                    listener.send_to(&data, client).await?;
+                }
+            }
+            Ok(()) = toxics.changed() => {
+                // Pick up toxics added or removed through the API while the session is open
+                let current = toxics.borrow_and_update().clone();
+                upstream_pipeline.refresh(&current);
+                downstream_pipeline.refresh(&current);
+            }
+            _ = &mut idle => return Ok(()),
+        }
+    }
+}
+
+// One direction of a session: its toxics and the datagrams they are holding back
+struct Pipeline<'a> {
+    route: &'a Route,
+    // Number of the session within its listener, to seed its toxics
+    accepted: u64,
+    stats: &'a ConnectionStats,
+    stream: Stream,
+    direction: String,
+    active: Vec<ActiveToxic>,
+    // Datagrams by the time they are due, in the order they were scheduled for equal times
+    pending: BinaryHeap<Reverse<(Instant, u64, Vec<u8>)>>,
+    scheduled: u64,
+}
+
+impl<'a> Pipeline<'a> {
+    fn new(
+        route: &'a Route,
+        accepted: u64,
+        stats: &'a ConnectionStats,
+        stream: Stream,
+        toxics: &[Arc<ToxicWrapper>],
+    ) -> Self {
+        let active = Proxy::refresh_toxics(Vec::new(), toxics, accepted, stream);
+        stats.set_toxics(stream, &active);
+        Pipeline {
+            route,
+            accepted,
+            stats,
+            stream,
+            direction: stream.to_string(),
+            active,
+            pending: BinaryHeap::new(),
+            scheduled: 0,
+        }
+    }
+
+    fn refresh(&mut self, toxics: &[Arc<ToxicWrapper>]) {
+        let previous = std::mem::take(&mut self.active);
+        self.active = Proxy::refresh_toxics(previous, toxics, self.accepted, self.stream);
+        self.stats.set_toxics(self.stream, &self.active);
+    }
+
+    fn capture(&self, event: Event) {
+        if let Some(capture) = &self.route.capture {
+            capture.record(self.stats.id, event);
+        }
+    }
+
+    // Runs a datagram through the enabled toxics and schedules whatever copies they pass on
+    fn push(&mut self, data: Vec<u8>) {
+        let proxy = self.route.proxy.as_str();
+        Proxy::record_activations(proxy, &self.direction, &self.active);
+        self.capture(Event::Received {
+            stream: self.stream,
+            data: &data,
+        });
+
+        let mut datagrams = vec![Datagram {
+            data,
+            delay: Duration::ZERO,
+        }];
+        for active in self.active.iter_mut().filter(|active| active.enabled) {
+            datagrams = datagrams
+                .into_iter()
+                .flat_map(|datagram| active.toxic.toxic.datagram(datagram, &mut active.state))
+                .collect();
+        }
+
+        let latency = metrics::LATENCY_HISTOGRAM.with_label_values(&[proxy, &self.direction]);
+        let now = Instant::now();
+        for datagram in datagrams {
+            latency.observe(datagram.delay.as_secs_f64());
+            self.pending.push(Reverse((
+                now + datagram.delay,
+                self.scheduled,
+                datagram.data,
+            )));
+            self.scheduled += 1;
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.peek().map(|Reverse((due, _, _))| *due)
+    }
+
+    fn take_due(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut due = Vec::new();
+        while self.next_due().is_some_and(|next| next <= now) {
+            let Reverse((_, _, data)) = self.pending.pop().expect("peeked datagram");
+            self.capture(Event::Sent {
+                stream: self.stream,
+                data: &data,
+            });
+            self.stats.forwarded(self.stream, data.len());
+            due.push(data);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{Protocol, ProxyConfig};
+    use crate::toxic::{Jitter, ToxicConfig, ToxicKind};
+
+    async fn start_echo_server() -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buffer = vec![0; MAX_DATAGRAM];
+            while let Ok((n, peer)) = socket.recv_from(&mut buffer).await {
+                let _ = socket.send_to(&buffer[..n], peer).await;
+            }
+        });
+        address
+    }
+
+    async fn start_proxy(upstream: &str) -> Proxy {
+        let mut proxy = Proxy::new(ProxyConfig {
+            name: "udp".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: upstream.to_string(),
+            protocol: Protocol::Udp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        proxy.start().await.unwrap();
+        proxy
+    }
+
+    async fn connect(proxy: &Proxy) -> UdpSocket {
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(&proxy.listen).await.unwrap();
+        client
+    }
+
+    fn toxic(stream: Stream, kind: ToxicKind) -> ToxicConfig {
+        ToxicConfig {
+            stream,
+            ..kind.into()
+        }
+    }
+
+    async fn receive(client: &UdpSocket, wait: Duration) -> Option<Vec<u8>> {
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        let n = time::timeout(wait, client.recv(&mut buffer))
+            .await
+            .ok()?
+            .unwrap();
+        Some(buffer[..n].to_vec())
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_forwards_datagrams_both_ways() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        let client = connect(&proxy).await;
+
+        client.send(b"hello").await.unwrap();
+
+        let reply = receive(&client, Duration::from_secs(1)).await;
+        assert_eq!(reply.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(proxy.connections.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_drops_datagrams() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(
+            Stream::Upstream,
+            ToxicKind::Drop { probability: 1.0 },
+        ));
+        let client = connect(&proxy).await;
+
+        client.send(b"hello").await.unwrap();
+
+        assert_eq!(receive(&client, Duration::from_millis(200)).await, None);
+        // Dropped datagrams never reach the upstream, so they don't count
+        assert_eq!(proxy.connections()[0].bytes_upstream, 0);
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_duplicates_datagrams() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(
+            Stream::Downstream,
+            ToxicKind::Duplicate { probability: 1.0 },
+        ));
+        let client = connect(&proxy).await;
+
+        client.send(b"hello").await.unwrap();
+
+        let wait = Duration::from_secs(1);
+        assert_eq!(receive(&client, wait).await.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(receive(&client, wait).await.as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_delays_datagrams_with_latency() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(
+            Stream::Upstream,
+            ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE },
+        ));
+        let client = connect(&proxy).await;
+
+        let start = std::time::Instant::now();
+        client.send(b"hello").await.unwrap();
+
+        assert!(receive(&client, Duration::from_secs(1)).await.is_some());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_reorders_datagrams() {
+        let upstream = start_echo_server().await;
+        let proxy = start_proxy(&upstream).await;
+        proxy.add_toxic(toxic(
+            Stream::Upstream,
+            ToxicKind::Reorder {
+                probability: 1.0,
+                delay_ms: 200,
+            },
+        ));
+        let client = connect(&proxy).await;
+
+        // Hold back the first datagram, then let the second one through right away
+        client.send(b"first").await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+        proxy.remove_toxic("reorder_upstream");
+        time::sleep(Duration::from_millis(50)).await;
+        client.send(b"second").await.unwrap();
+
+        let wait = Duration::from_secs(1);
+        assert_eq!(
+            receive(&client, wait).await.as_deref(),
+            Some(&b"second"[..])
+        );
+        assert_eq!(receive(&client, wait).await.as_deref(), Some(&b"first"[..]));
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_stop_ends_sessions() {
+        let upstream = start_echo_server().await;
+        let mut proxy = start_proxy(&upstream).await;
+        let client = connect(&proxy).await;
+        client.send(b"hello").await.unwrap();
+        assert!(receive(&client, Duration::from_secs(1)).await.is_some());
+
+        proxy.stop().await;
+
+        assert!(proxy.connections.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn udp_proxy_rejects_stream_toxics() {
+        let proxy = Proxy::new(ProxyConfig {
+            name: "udp".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: "127.0.0.1:8476".to_string(),
+            protocol: Protocol::Udp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+
+        let error = proxy
+            .check_toxic(&ToxicKind::Timeout { timeout_ms: 0 }.into())
+            .unwrap_err();
+
+        assert_eq!(error, "timeout toxics are not supported on udp proxies");
+        assert!(proxy
+            .check_toxic(&ToxicKind::Drop { probability: 0.5 }.into())
+            .is_ok());
+    }
+}
@@ -1,52 +1,245 @@
-use crate::proxy::ProxyState;
-use crate::toxic::{Toxic, ToxicConfig};
-use crate::toxics::corrupt::CorruptToxic;
-use crate::toxics::latency::LatencyToxic;
-use crate::toxics::slow_close::SlowCloseToxic;
+use crate::config::Config;
+use crate::events::{self, EventRecord, ProxyEvent};
+use crate::metrics;
+use crate::proxy::{ConnectionInfo, Proxy, ProxyConfig, ProxyState};
+use crate::scenario::{Scenario, ScenarioStatus, Scenarios};
+use crate::toxic::{ToxicConfig, ToxicWrapper};
 use axum::{
-    extract::State,
-    Json,
+    extract::{FromRef, Path, Query, State},
+    http::StatusCode,
+    response::sse::{self, KeepAlive, Sse},
+    routing::{delete, get, post},
+    Json, Router,
 };
+use futures::stream::{self, Stream};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
-use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tower_http::cors::CorsLayer;
+
+type ApiError = (StatusCode, String);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateProxyRequest {
+    pub listen: Option<String>,
+    pub upstream: Option<String>,
+    pub enabled: Option<bool>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ProxyConfig {
-    name: String,
-    listen: String,
-    upstream: String,
+pub struct ProxyResponse {
+    #[serde(flatten)]
+    pub config: ProxyConfig,
+    pub toxics: Vec<ToxicResponse>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct CreateToxicRequest {
-    proxy: String,
-    config: ToxicConfig,
+pub struct CreateToxicRequest {
+    pub proxy: String,
+    pub config: ToxicConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ToxicResponse {
-    proxy: String,
-    toxic_type: String,
-    config: ToxicConfig,
+pub struct ToxicResponse {
+    pub proxy: String,
+    pub toxic_type: String,
+    pub config: ToxicConfig,
+}
+
+// Everything the handlers share. Each handler takes the part it needs.
+#[derive(Clone)]
+struct ApiState {
+    proxies: ProxyState,
+    scenarios: Scenarios,
+}
+
+impl FromRef<ApiState> for ProxyState {
+    fn from_ref(state: &ApiState) -> Self {
+        Arc::clone(&state.proxies)
+    }
+}
+
+impl FromRef<ApiState> for Scenarios {
+    fn from_ref(state: &ApiState) -> Self {
+        state.scenarios.clone()
+    }
+}
+
+pub fn router(proxies: ProxyState, scenarios: Scenarios) -> Router {
+    Router::new()
+        .route("/proxies", get(list_proxies).post(create_proxy))
+        .route(
+            "/proxies/:name",
+            get(get_proxy).post(update_proxy).delete(delete_proxy),
+        )
+        .route(
+            "/proxies/:name/toxics",
+            get(list_proxy_toxics).post(create_proxy_toxic),
+        )
+        .route(
+            "/proxies/:name/toxics/:toxic",
+            get(get_toxic).post(update_toxic).delete(delete_toxic),
+        )
+        .route("/proxies/:name/connections", get(list_connections))
+        .route("/proxies/:name/connections/:id", delete(delete_connection))
+        .route("/toxics", get(list_toxics).post(add_toxic))
+        .route("/scenarios", get(list_scenarios).post(start_scenario))
+        .route("/scenarios/:name", delete(stop_scenario))
+        .route("/populate", post(populate))
+        .route("/reset", post(reset))
+        .route("/metrics", get(get_metrics))
+        .route("/events", get(stream_events))
+        .layer(CorsLayer::permissive())
+        .with_state(ApiState { proxies, scenarios })
+}
+
+fn proxy_not_found(name: &str) -> ApiError {
+    (StatusCode::NOT_FOUND, format!("proxy not found: {}", name))
+}
+
+fn toxic_not_found(proxy: &str, name: &str) -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        format!("toxic not found: {}/{}", proxy, name),
+    )
+}
+
+fn toxic_response(proxy: &Proxy, toxic: &ToxicWrapper) -> ToxicResponse {
+    ToxicResponse {
+        proxy: proxy.name.clone(),
+        toxic_type: toxic.toxic.get_type(),
+        config: toxic.config(),
+    }
+}
+
+fn toxic_responses(proxy: &Proxy) -> Vec<ToxicResponse> {
+    proxy
+        .toxics
+        .borrow()
+        .iter()
+        .map(|toxic| toxic_response(proxy, toxic))
+        .collect()
+}
+
+// Toxics are only accepted for running proxies, so they take effect immediately
+fn insert_toxic(proxy: &Proxy, config: ToxicConfig) -> Result<ToxicResponse, ApiError> {
+    proxy
+        .check_toxic(&config)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let toxic = proxy.add_toxic(config).ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            "toxic already exists on this proxy".to_string(),
+        )
+    })?;
+    Ok(toxic_response(proxy, &toxic))
+}
+
+fn proxy_response(proxy: &Proxy) -> ProxyResponse {
+    ProxyResponse {
+        config: proxy.config(),
+        toxics: toxic_responses(proxy),
+    }
 }
 
 // REST API handlers
+async fn list_proxies(
+    State(state): State<ProxyState>,
+) -> Json<HashMap<String, ProxyResponse>> {
+    let state = state.lock().await;
+    let proxies = state
+        .iter()
+        .map(|(name, proxy)| (name.clone(), proxy_response(proxy)))
+        .collect();
+
+    Json(proxies)
+}
+
+async fn create_proxy(
+    State(state): State<ProxyState>,
+    Json(config): Json<ProxyConfig>,
+) -> Result<(StatusCode, Json<ProxyResponse>), ApiError> {
+    let mut state = state.lock().await;
+    if state.contains_key(&config.name) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("proxy already exists: {}", config.name),
+        ));
+    }
+
+    let mut proxy = Proxy::new(config);
+    proxy.start().await.map_err(start_error)?;
+
+    let response = proxy_response(&proxy);
+    events::publish(&proxy.name, ProxyEvent::ProxyCreated);
+    state.insert(proxy.name.clone(), proxy);
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+async fn get_proxy(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+) -> Result<Json<ProxyResponse>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    Ok(Json(proxy_response(proxy)))
+}
+
+async fn update_proxy(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateProxyRequest>,
+) -> Result<Json<ProxyResponse>, ApiError> {
+    let mut state = state.lock().await;
+    let proxy = state.get_mut(&name).ok_or_else(|| proxy_not_found(&name))?;
+
+    // Restart the listener with the new settings, falling back to the old ones if that fails
+    let previous = proxy.config();
+    proxy.stop().await;
+    proxy.listen = request.listen.unwrap_or(previous.listen.clone());
+    proxy.upstream = request.upstream.unwrap_or(previous.upstream.clone());
+    proxy.enabled = request.enabled.unwrap_or(previous.enabled);
+    if !proxy.enabled {
+        // A disabled proxy acts like a dependency that is down, so open connections go too
+        proxy.close_connections();
+    }
+
+    if let Err(e) = proxy.start().await {
+        proxy.listen = previous.listen;
+        proxy.upstream = previous.upstream;
+        proxy.enabled = previous.enabled;
+        let _ = proxy.start().await;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
+    Ok(Json(proxy_response(proxy)))
+}
+
+async fn delete_proxy(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let mut proxy = state
+        .lock()
+        .await
+        .remove(&name)
+        .ok_or_else(|| proxy_not_found(&name))?;
+
+    proxy.stop().await;
+    // Connections of a deleted proxy would otherwise keep forwarding to the upstream
+    proxy.close_connections();
+    events::publish(&name, ProxyEvent::ProxyDeleted);
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn list_toxics(
     State(state): State<ProxyState>,
 ) -> Json<Vec<ToxicResponse>> {
-    let state = state.lock().unwrap();
-    let mut responses = Vec::new();
-
-    for (proxy_name, toxics) in state.iter() {
-        for toxic in toxics {
-            responses.push(ToxicResponse {
-                proxy: proxy_name.clone(),
-                toxic_type: toxic.get_type(),
-                config: toxic.get_config(),
-            });
-        }
-    }
+    let state = state.lock().await;
+    let responses = state.values().flat_map(toxic_responses).collect();
 
     Json(responses)
 }
@@ -54,29 +247,720 @@ async fn list_toxics(
 async fn add_toxic(
     State(state): State<ProxyState>,
     Json(request): Json<CreateToxicRequest>,
-) -> Json<ToxicResponse> {
-    let toxic: Arc<dyn Toxic> = match request.config {
-        ToxicConfig::Latency { latency_ms } => Arc::new(LatencyToxic {
-            latency: Duration::from_millis(latency_ms),
-        }),
-        ToxicConfig::Corrupt { probability } => Arc::new(CorruptToxic { probability }),
-        ToxicConfig::SlowClose { delay_ms } => Arc::new(SlowCloseToxic {
-            delay: Duration::from_millis(delay_ms),
-        }),
-    };
+) -> Result<Json<ToxicResponse>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state
+        .get(&request.proxy)
+        .ok_or_else(|| proxy_not_found(&request.proxy))?;
 
-    let mut state = state.lock().unwrap();
-    let toxics = state
-        .entry(request.proxy.clone())
-        .or_insert_with(Vec::new);
+    Ok(Json(insert_toxic(proxy, request.config)?))
+}
+
+async fn list_proxy_toxics(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ToxicResponse>>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    Ok(Json(toxic_responses(proxy)))
+}
+
+async fn create_proxy_toxic(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+    Json(config): Json<ToxicConfig>,
+) -> Result<Json<ToxicResponse>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    Ok(Json(insert_toxic(proxy, config)?))
+}
+
+async fn get_toxic(
+    State(state): State<ProxyState>,
+    Path((name, toxic)): Path<(String, String)>,
+) -> Result<Json<ToxicResponse>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    let toxic = proxy
+        .toxic(&toxic)
+        .ok_or_else(|| toxic_not_found(&name, &toxic))?;
+    Ok(Json(toxic_response(proxy, &toxic)))
+}
+
+// Replaces the toxic's configuration, keeping its name. Open connections pick up the new
+// configuration from their next chunk of data.
+async fn update_toxic(
+    State(state): State<ProxyState>,
+    Path((name, toxic)): Path<(String, String)>,
+    Json(config): Json<ToxicConfig>,
+) -> Result<Json<ToxicResponse>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    proxy
+        .check_toxic(&config)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let updated = proxy
+        .update_toxic(&toxic, config)
+        .ok_or_else(|| toxic_not_found(&name, &toxic))?;
+    Ok(Json(toxic_response(proxy, &updated)))
+}
+
+async fn delete_toxic(
+    State(state): State<ProxyState>,
+    Path((name, toxic)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    if !proxy.remove_toxic(&toxic) {
+        return Err(toxic_not_found(&name, &toxic));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_connections(
+    State(state): State<ProxyState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ConnectionInfo>>, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    Ok(Json(proxy.connections()))
+}
+
+// Drops a single connection, closing both of its sides. Other connections are left alone.
+async fn delete_connection(
+    State(state): State<ProxyState>,
+    Path((name, id)): Path<(String, u64)>,
+) -> Result<StatusCode, ApiError> {
+    let state = state.lock().await;
+    let proxy = state.get(&name).ok_or_else(|| proxy_not_found(&name))?;
+    if !proxy.close_connection(id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("connection not found: {}/{}", name, id),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let response = ToxicResponse {
-        proxy: request.proxy,
-        toxic_type: toxic.get_type(),
-        config: toxic.get_config(),
+// Bad settings, such as unreadable TLS certificates, are the client's fault, anything else ours
+fn start_error(e: std::io::Error) -> ApiError {
+    let status = match e.kind() {
+        std::io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
     };
+    (status, e.to_string())
+}
+
+async fn list_scenarios(State(scenarios): State<Scenarios>) -> Json<Vec<ScenarioStatus>> {
+    Json(scenarios.list())
+}
+
+// Starts running a timeline of toxic changes. Its steps are checked against the proxies first.
+async fn start_scenario(
+    State(proxies): State<ProxyState>,
+    State(scenarios): State<Scenarios>,
+    Json(scenario): Json<Scenario>,
+) -> Result<(StatusCode, Json<ScenarioStatus>), ApiError> {
+    match scenarios.start(scenario, &proxies).await {
+        Ok(status) => Ok((StatusCode::CREATED, Json(status))),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            Err((StatusCode::CONFLICT, e.to_string()))
+        }
+        Err(e) => Err(start_error(e)),
+    }
+}
+
+// Stops a scenario before its remaining steps. The toxics it added so far stay in place.
+async fn stop_scenario(
+    State(scenarios): State<Scenarios>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !scenarios.stop(&name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("scenario not found: {}", name),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Replaces every proxy with the ones in the request, using the schema of the config file
+async fn populate(
+    State(state): State<ProxyState>,
+    Json(config): Json<Config>,
+) -> Result<Json<HashMap<String, ProxyResponse>>, ApiError> {
+    let mut state = state.lock().await;
+    config.apply(&mut state).await.map_err(start_error)?;
+
+    let proxies = state
+        .iter()
+        .map(|(name, proxy)| (name.clone(), proxy_response(proxy)))
+        .collect();
+    Ok(Json(proxies))
+}
+
+// Removes every toxic and enables every proxy again. Every proxy is reset before any is
+// started, so one that fails to start doesn't keep the others from being reset.
+async fn reset(State(state): State<ProxyState>) -> Result<StatusCode, ApiError> {
+    let mut state = state.lock().await;
+    for proxy in state.values_mut() {
+        proxy.clear_toxics();
+        proxy.enabled = true;
+    }
+
+    let mut failures = Vec::new();
+    for proxy in state.values_mut() {
+        if let Err(e) = proxy.start().await {
+            failures.push(format!("{}: {}", proxy.name, e));
+        }
+    }
+    if !failures.is_empty() {
+        failures.sort();
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, failures.join("; ")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_metrics() -> String {
+    metrics::encode()
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    // Only stream the events of this proxy
+    proxy: Option<String>,
+}
 
-    toxics.push(toxic);
-    Json(response)
+// Streams events as they happen, as server-sent events with a JSON record each. A client that
+// falls too far behind gets a comment saying how many it missed, then carries on.
+async fn stream_events(
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let events = stream::unfold(events::subscribe(), move |mut events| {
+        let proxy = query.proxy.clone();
+        async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(record) if proxy.as_ref().is_some_and(|proxy| *proxy != record.proxy) => {
+                        continue
+                    }
+                    Ok(record) => event_data(&record),
+                    Err(RecvError::Lagged(missed)) => {
+                        sse::Event::default().comment(format!("missed {} events", missed))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok(event), events));
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn event_data(record: &EventRecord) -> sse::Event {
+    sse::Event::default()
+        .json_data(record)
+        .expect("event records serialize")
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::Protocol;
+    use crate::toxic::{Jitter, Stream, ToxicKind};
+    use tokio::sync::Mutex;
+
+    fn proxy_config(name: &str) -> ProxyConfig {
+        ProxyConfig {
+            name: name.to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: "127.0.0.1:8476".to_string(),
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        }
+    }
+
+    async fn add_proxy(state: &ProxyState, name: &str) {
+        let result = create_proxy(State(Arc::clone(state)), Json(proxy_config(name))).await;
+        assert_eq!(result.unwrap().0, StatusCode::CREATED);
+    }
+
+    fn latency(latency_ms: u64) -> ToxicKind {
+        ToxicKind::Latency {
+            latency_ms,
+            jitter: Jitter::NONE,
+        }
+    }
+
+    fn toxic_config(kind: ToxicKind) -> ToxicConfig {
+        ToxicConfig {
+            name: String::new(),
+            stream: Stream::Downstream,
+            toxicity: 1.0,
+            seed: None,
+            kind,
+        }
+    }
+
+    async fn state_with_proxy(name: &str) -> ProxyState {
+        let state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+        add_proxy(&state, name).await;
+        state
+    }
+
+    #[tokio::test]
+    async fn create_proxy_starts_listener() {
+        let state = state_with_proxy("pg").await;
+
+        let Json(response) = get_proxy(State(Arc::clone(&state)), Path("pg".to_string()))
+            .await
+            .unwrap();
+
+        assert!(tokio::net::TcpStream::connect(&response.config.listen).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn create_proxy_rejects_duplicate_name() {
+        let state = state_with_proxy("pg").await;
+
+        let result = create_proxy(State(state), Json(proxy_config("pg"))).await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::CONFLICT));
+    }
+
+    #[tokio::test]
+    async fn list_proxies_returns_every_proxy() {
+        let state = state_with_proxy("pg").await;
+        add_proxy(&state, "redis").await;
+
+        let Json(proxies) = list_proxies(State(state)).await;
+
+        assert_eq!(proxies.len(), 2);
+        assert!(proxies.contains_key("pg"));
+        assert!(proxies.contains_key("redis"));
+    }
+
+    #[tokio::test]
+    async fn update_proxy_changes_upstream() {
+        let state = state_with_proxy("pg").await;
+        let request = UpdateProxyRequest {
+            upstream: Some("127.0.0.1:5432".to_string()),
+            ..Default::default()
+        };
+
+        let Json(response) = update_proxy(State(state), Path("pg".to_string()), Json(request))
+            .await
+            .unwrap();
+
+        assert_eq!(response.config.upstream, "127.0.0.1:5432");
+    }
+
+    #[tokio::test]
+    async fn update_proxy_disables_and_enables_listener() {
+        let state = state_with_proxy("pg").await;
+        let toggle = |enabled| UpdateProxyRequest {
+            enabled: Some(enabled),
+            ..Default::default()
+        };
+
+        let Json(response) =
+            update_proxy(State(Arc::clone(&state)), Path("pg".to_string()), Json(toggle(false)))
+                .await
+                .unwrap();
+        assert!(!response.config.enabled);
+        assert!(tokio::net::TcpStream::connect(&response.config.listen).await.is_err());
+
+        let Json(response) =
+            update_proxy(State(Arc::clone(&state)), Path("pg".to_string()), Json(toggle(true)))
+                .await
+                .unwrap();
+        assert!(response.config.enabled);
+        assert!(tokio::net::TcpStream::connect(&response.config.listen).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn delete_proxy_closes_listener() {
+        let state = state_with_proxy("pg").await;
+        let listen = state.lock().await["pg"].listen.clone();
+
+        let status = delete_proxy(State(Arc::clone(&state)), Path("pg".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(state.lock().await.is_empty());
+        assert!(tokio::net::TcpStream::connect(&listen).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_proxy_drops_open_connections() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+        let config = ProxyConfig {
+            upstream: upstream.local_addr().unwrap().to_string(),
+            ..proxy_config("main")
+        };
+        let created = create_proxy(State(Arc::clone(&state)), Json(config)).await;
+        assert_eq!(created.unwrap().0, StatusCode::CREATED);
+        let listen = state.lock().await["main"].listen.clone();
+        let mut client = tokio::net::TcpStream::connect(&listen).await.unwrap();
+        let (_server, _) = upstream.accept().await.unwrap();
+
+        let status = delete_proxy(State(state), Path("main".to_string())).await.unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // The upstream stays open, so only the proxy can have closed the client's side
+        let mut buffer = [0; 16];
+        let read = tokio::time::timeout(
+            std::time::Duration::from_secs(2),
+            tokio::io::AsyncReadExt::read(&mut client, &mut buffer),
+        )
+        .await;
+        assert_eq!(read.unwrap().unwrap_or(0), 0);
+    }
+
+    #[tokio::test]
+    async fn add_toxic_applies_to_running_proxy() {
+        let state = state_with_proxy("main").await;
+        let request = CreateToxicRequest {
+            proxy: "main".to_string(),
+            config: toxic_config(latency(100)),
+        };
+
+        let Json(response) = add_toxic(State(Arc::clone(&state)), Json(request))
+            .await
+            .unwrap();
+
+        assert_eq!(response.toxic_type, "latency");
+        assert_eq!(state.lock().await["main"].toxics.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn add_toxic_rejects_unknown_proxy() {
+        let state = state_with_proxy("main").await;
+        let request = CreateToxicRequest {
+            proxy: "missing".to_string(),
+            config: toxic_config(ToxicKind::Corrupt { probability: 0.5 }),
+        };
+
+        let result = add_toxic(State(Arc::clone(&state)), Json(request)).await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+        assert!(state.lock().await["main"].toxics.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn add_toxic_rejects_invalid_toxicity() {
+        let state = state_with_proxy("main").await;
+        let request = CreateToxicRequest {
+            proxy: "main".to_string(),
+            config: ToxicConfig {
+                toxicity: 1.5,
+                seed: None,
+                ..toxic_config(latency(100))
+            },
+        };
+
+        let result = add_toxic(State(Arc::clone(&state)), Json(request)).await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+        assert!(state.lock().await["main"].toxics.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_toxics_returns_toxics_for_each_proxy() {
+        let state = state_with_proxy("main").await;
+        state.lock().await["main"].add_toxic(toxic_config(ToxicKind::Corrupt { probability: 0.5 }));
+
+        let Json(responses) = list_toxics(State(state)).await;
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].proxy, "main");
+        assert_eq!(responses[0].toxic_type, "corrupt");
+    }
+
+    #[tokio::test]
+    async fn add_toxic_rejects_duplicate_name() {
+        let state = state_with_proxy("main").await;
+        let request = || CreateToxicRequest {
+            proxy: "main".to_string(),
+            config: toxic_config(latency(100)),
+        };
+        assert!(add_toxic(State(Arc::clone(&state)), Json(request())).await.is_ok());
+
+        let result = add_toxic(State(Arc::clone(&state)), Json(request())).await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::CONFLICT));
+    }
+
+    #[tokio::test]
+    async fn create_proxy_toxic_uses_given_name() {
+        let state = state_with_proxy("main").await;
+        let config = ToxicConfig {
+            name: "lag".to_string(),
+            ..toxic_config(latency(100))
+        };
+
+        let created =
+            create_proxy_toxic(State(Arc::clone(&state)), Path("main".to_string()), Json(config))
+                .await;
+        assert!(created.is_ok());
+
+        let Json(response) = get_toxic(
+            State(Arc::clone(&state)),
+            Path(("main".to_string(), "lag".to_string())),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.toxic_type, "latency");
+        assert_eq!(response.config.name, "lag");
+    }
+
+    #[tokio::test]
+    async fn create_proxy_toxic_rejects_stream_toxic_on_udp_proxy() {
+        let state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+        let config = ProxyConfig {
+            protocol: Protocol::Udp,
+            ..proxy_config("dns")
+        };
+        assert!(create_proxy(State(Arc::clone(&state)), Json(config)).await.is_ok());
+
+        let result = create_proxy_toxic(
+            State(Arc::clone(&state)),
+            Path("dns".to_string()),
+            Json(toxic_config(ToxicKind::SlowClose { delay_ms: 100 })),
+        )
+        .await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn update_toxic_replaces_configuration() {
+        let state = state_with_proxy("main").await;
+        state.lock().await["main"].add_toxic(toxic_config(latency(100)));
+
+        let Json(response) = update_toxic(
+            State(Arc::clone(&state)),
+            Path(("main".to_string(), "latency_downstream".to_string())),
+            Json(toxic_config(latency(500))),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.config.name, "latency_downstream");
+        assert!(matches!(
+            response.config.kind,
+            ToxicKind::Latency { latency_ms: 500, .. }
+        ));
+        assert_eq!(state.lock().await["main"].toxics.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn update_toxic_rejects_unknown_toxic() {
+        let state = state_with_proxy("main").await;
+
+        let result = update_toxic(
+            State(state),
+            Path(("main".to_string(), "missing".to_string())),
+            Json(toxic_config(latency(500))),
+        )
+        .await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn delete_toxic_removes_toxic() {
+        let state = state_with_proxy("main").await;
+        state.lock().await["main"].add_toxic(toxic_config(latency(100)));
+        let path = || Path(("main".to_string(), "latency_downstream".to_string()));
+
+        let status = delete_toxic(State(Arc::clone(&state)), path()).await.unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(state.lock().await["main"].toxics.borrow().is_empty());
+
+        let result = delete_toxic(State(state), path()).await;
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn delete_connection_drops_only_that_connection() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+        let config = ProxyConfig {
+            upstream: upstream.local_addr().unwrap().to_string(),
+            ..proxy_config("main")
+        };
+        let created = create_proxy(State(Arc::clone(&state)), Json(config)).await;
+        assert_eq!(created.unwrap().0, StatusCode::CREATED);
+        let listen = state.lock().await["main"].listen.clone();
+        let _first = tokio::net::TcpStream::connect(&listen).await.unwrap();
+        let _second = tokio::net::TcpStream::connect(&listen).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let path = || Path("main".to_string());
+        let listed = list_connections(State(Arc::clone(&state)), path()).await.unwrap();
+        assert_eq!(listed.len(), 2);
+
+        let id = listed[0].id;
+        let delete = || Path(("main".to_string(), id));
+        let status = delete_connection(State(Arc::clone(&state)), delete()).await.unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let listed = list_connections(State(Arc::clone(&state)), path()).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_ne!(listed[0].id, id);
+
+        let result = delete_connection(State(state), delete()).await;
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn start_scenario_checks_steps_and_names() {
+        let state = state_with_proxy("main").await;
+        let scenarios = Scenarios::default();
+        let scenario = |proxy: &str| -> Scenario {
+            serde_json::from_value(serde_json::json!({"name": "outage", "steps": [
+                {"at_ms": 60000, "proxy": proxy, "action": "reset"}]}))
+            .unwrap()
+        };
+        let start = |proxy: &str| {
+            start_scenario(
+                State(Arc::clone(&state)),
+                State(scenarios.clone()),
+                Json(scenario(proxy)),
+            )
+        };
+
+        assert_eq!(start("missing").await.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(start("main").await.unwrap().0, StatusCode::CREATED);
+        assert_eq!(start("main").await.err().map(|e| e.0), Some(StatusCode::CONFLICT));
+        assert_eq!(list_scenarios(State(scenarios.clone())).await.len(), 1);
+
+        let stop = || stop_scenario(State(scenarios.clone()), Path("outage".to_string()));
+        assert_eq!(stop().await.unwrap(), StatusCode::NO_CONTENT);
+        assert_eq!(stop().await.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn events_stream_proxy_and_toxic_changes() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let state: ProxyState = Arc::new(Mutex::new(HashMap::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = listener.local_addr().unwrap();
+        let app = router(Arc::clone(&state), Scenarios::default());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut stream = tokio::net::TcpStream::connect(api).await.unwrap();
+        let request = "GET /events?proxy=events_api HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        // Wait for the response headers, so the subscription is in place
+        while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buffer).await.unwrap();
+            received.extend_from_slice(&buffer[..n]);
+        }
+
+        add_proxy(&state, "other").await;
+        add_proxy(&state, "events_api").await;
+        state.lock().await["events_api"].add_toxic(toxic_config(latency(100)));
+
+        while !String::from_utf8_lossy(&received).contains("toxic_added") {
+            let read = stream.read(&mut buffer);
+            let n = tokio::time::timeout(std::time::Duration::from_secs(1), read)
+                .await
+                .unwrap()
+                .unwrap();
+            received.extend_from_slice(&buffer[..n]);
+        }
+        let received = String::from_utf8_lossy(&received);
+        assert!(received.contains("text/event-stream"));
+        assert!(received.contains(r#""event":"proxy_created""#));
+        assert!(received.contains(r#""toxic":{"name":"latency_downstream""#));
+        assert!(!received.contains(r#""proxy":"other""#));
+    }
+
+    #[tokio::test]
+    async fn populate_replaces_every_proxy() {
+        let state = state_with_proxy("main").await;
+        let config: Config = serde_json::from_str(
+            r#"{"proxies": [{"name": "pg", "listen": "127.0.0.1:0", "upstream": "127.0.0.1:5432",
+                "toxics": [{"type": "Latency", "latency_ms": 100}]}]}"#,
+        )
+        .unwrap();
+
+        let Json(proxies) = populate(State(Arc::clone(&state)), Json(config))
+            .await
+            .unwrap();
+
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies["pg"].toxics[0].config.name, "latency_downstream");
+        assert!(!state.lock().await.contains_key("main"));
+    }
+
+    #[tokio::test]
+    async fn populate_rejects_invalid_toxic() {
+        let state = state_with_proxy("main").await;
+        let config: Config = serde_json::from_str(
+            r#"{"proxies": [{"name": "pg", "listen": "127.0.0.1:0", "upstream": "127.0.0.1:5432",
+                "toxics": [{"type": "Latency", "latency_ms": 100, "toxicity": 2.0}]}]}"#,
+        )
+        .unwrap();
+
+        let result = populate(State(Arc::clone(&state)), Json(config)).await;
+
+        assert_eq!(result.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+        assert!(state.lock().await.contains_key("main"));
+    }
+
+    #[tokio::test]
+    async fn reset_removes_toxics_and_enables_proxies() {
+        let state = state_with_proxy("main").await;
+        add_proxy(&state, "pg").await;
+        state.lock().await["main"].add_toxic(toxic_config(latency(100)));
+        let disable = UpdateProxyRequest {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        let disabled =
+            update_proxy(State(Arc::clone(&state)), Path("pg".to_string()), Json(disable)).await;
+        assert!(disabled.is_ok());
+
+        let status = reset(State(Arc::clone(&state))).await.unwrap();
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let state = state.lock().await;
+        assert!(state["main"].toxics.borrow().is_empty());
+        assert!(state["pg"].enabled);
+        assert!(tokio::net::TcpStream::connect(&state["pg"].listen).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reset_resets_every_proxy_and_reports_failures() {
+        let state = state_with_proxy("main").await;
+        add_proxy(&state, "pg").await;
+        state.lock().await["main"].add_toxic(toxic_config(latency(100)));
+        let disable = UpdateProxyRequest {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        let disabled =
+            update_proxy(State(Arc::clone(&state)), Path("pg".to_string()), Json(disable)).await;
+        assert!(disabled.is_ok());
+        // Something else takes the address of the disabled proxy, so it can't start again
+        let listen = state.lock().await["pg"].listen.clone();
+        let _taken = tokio::net::TcpListener::bind(&listen).await.unwrap();
+
+        let (status, message) = reset(State(Arc::clone(&state))).await.unwrap_err();
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(message.starts_with("pg: "));
+        let state = state.lock().await;
+        assert!(state["main"].toxics.borrow().is_empty());
+        assert!(state["pg"].enabled);
+    }
+}
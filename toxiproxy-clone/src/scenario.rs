@@ -0,0 +1,380 @@
+use crate::proxy::{Proxy, ProxyState};
+use crate::toxic::{ToxicConfig, ToxicWrapper};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::AbortHandle;
+use tokio::time::{self, Instant};
+
+// A timeline of changes to the toxics of named proxies, submitted through `POST /scenarios` or
+// loaded from a file with `--scenario`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    // Time from the start of the scenario. Steps due at the same time run in the order given.
+    pub at_ms: u64,
+    pub proxy: String,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Add { toxic: ToxicConfig },
+    // Replaces the toxic with the same name, which defaults to the type and stream as for `add`
+    Update { toxic: ToxicConfig },
+    Remove { toxic: String },
+    // Removes every toxic of the proxy
+    Reset,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Scenario {
+    // Reads a TOML file when the extension says so, and JSON otherwise
+    pub fn load(path: &Path) -> io::Result<Scenario> {
+        let contents = std::fs::read_to_string(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            toml::from_str(&contents).map_err(|e| invalid(e.to_string()))
+        } else {
+            serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))
+        }
+    }
+
+    // Checks every step against the proxies as they are now, so a typo is reported when the
+    // scenario is submitted rather than halfway through it
+    fn validate(&self, proxies: &HashMap<String, Proxy>) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("scenario name must not be empty".to_string());
+        }
+        for step in &self.steps {
+            let proxy = proxies
+                .get(&step.proxy)
+                .ok_or_else(|| format!("proxy not found: {}", step.proxy))?;
+            if let Action::Add { toxic } | Action::Update { toxic } = &step.action {
+                proxy.check_toxic(toxic)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self, proxies: ProxyState, completed: Arc<AtomicUsize>) {
+        let start = Instant::now();
+        for step in self.steps {
+            time::sleep_until(start + Duration::from_millis(step.at_ms)).await;
+            if let Err(e) = step.apply(&*proxies.lock().await) {
+                eprintln!(
+                    "Scenario {} step at {}ms failed: {}",
+                    self.name, step.at_ms, e
+                );
+            }
+            completed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Step {
+    // Proxies and toxics can change through the API while a scenario runs, so a step that no
+    // longer fits is reported and skipped
+    fn apply(&self, proxies: &HashMap<String, Proxy>) -> Result<(), String> {
+        let proxy = proxies
+            .get(&self.proxy)
+            .ok_or_else(|| format!("proxy not found: {}", self.proxy))?;
+        match &self.action {
+            Action::Add { toxic } => {
+                proxy
+                    .add_toxic(toxic.clone())
+                    .ok_or_else(|| "toxic already exists on this proxy".to_string())?;
+            }
+            Action::Update { toxic } => {
+                let name = ToxicWrapper::new(toxic.clone()).name;
+                proxy
+                    .update_toxic(&name, toxic.clone())
+                    .ok_or_else(|| format!("toxic not found: {}", name))?;
+            }
+            Action::Remove { toxic } => {
+                if !proxy.remove_toxic(toxic) {
+                    return Err(format!("toxic not found: {}", toxic));
+                }
+            }
+            Action::Reset => proxy.clear_toxics(),
+        }
+        Ok(())
+    }
+}
+
+// A running scenario, as listed by the REST API
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScenarioStatus {
+    #[serde(flatten)]
+    pub scenario: Scenario,
+    // Microseconds since the Unix epoch
+    pub started_us: u64,
+    // Number of steps that have run so far
+    pub completed: usize,
+}
+
+struct Running {
+    scenario: Scenario,
+    started: SystemTime,
+    completed: Arc<AtomicUsize>,
+    task: AbortHandle,
+}
+
+impl Running {
+    fn status(&self) -> ScenarioStatus {
+        let started_us = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        ScenarioStatus {
+            scenario: self.scenario.clone(),
+            started_us,
+            completed: self.completed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Scenarios that are running, by name. A scenario is forgotten once its last step has run.
+#[derive(Clone, Default)]
+pub struct Scenarios {
+    running: Arc<Mutex<HashMap<String, Running>>>,
+}
+
+impl Scenarios {
+    // Starts running the scenario on a background task. An invalid scenario is reported as
+    // `InvalidInput`, and one named like a scenario that is still running as `AlreadyExists`.
+    pub async fn start(
+        &self,
+        mut scenario: Scenario,
+        proxies: &ProxyState,
+    ) -> io::Result<ScenarioStatus> {
+        scenario.validate(&*proxies.lock().await).map_err(invalid)?;
+        scenario.steps.sort_by_key(|step| step.at_ms);
+
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(&scenario.name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("scenario already running: {}", scenario.name),
+            ));
+        }
+
+        let completed = Arc::new(AtomicUsize::new(0));
+        let name = scenario.name.clone();
+        let task = tokio::spawn({
+            let scenarios = self.clone();
+            let run = scenario
+                .clone()
+                .run(Arc::clone(proxies), Arc::clone(&completed));
+            let name = name.clone();
+            let completed = Arc::clone(&completed);
+            async move {
+                run.await;
+                // Unless it was stopped and another scenario took over the name meanwhile
+                let mut running = scenarios.running.lock().unwrap();
+                if running
+                    .get(&name)
+                    .is_some_and(|r| Arc::ptr_eq(&r.completed, &completed))
+                {
+                    running.remove(&name);
+                }
+            }
+        });
+        let entry = Running {
+            scenario,
+            started: SystemTime::now(),
+            completed,
+            task: task.abort_handle(),
+        };
+        let status = entry.status();
+        // The lock is still held, so the task can't finish and deregister before this
+        running.insert(name, entry);
+        Ok(status)
+    }
+
+    // Running scenarios by name
+    pub fn list(&self) -> Vec<ScenarioStatus> {
+        let mut scenarios: Vec<ScenarioStatus> = self
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .map(Running::status)
+            .collect();
+        scenarios.sort_by(|a, b| a.scenario.name.cmp(&b.scenario.name));
+        scenarios
+    }
+
+    // Stops a scenario before its remaining steps, leaving the toxics as they are. Returns
+    // whether it was running.
+    pub fn stop(&self, name: &str) -> bool {
+        match self.running.lock().unwrap().remove(name) {
+            Some(running) => {
+                running.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{Protocol, ProxyConfig};
+    use crate::toxic::{Jitter, ToxicKind};
+
+    fn proxies(name: &str) -> ProxyState {
+        let proxy = Proxy::new(ProxyConfig {
+            name: name.to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            upstream: "127.0.0.1:8476".to_string(),
+            protocol: Protocol::Tcp,
+            enabled: true,
+            tls: None,
+            capture: None,
+        });
+        let proxies = HashMap::from([(name.to_string(), proxy)]);
+        Arc::new(tokio::sync::Mutex::new(proxies))
+    }
+
+    fn step(at_ms: u64, action: Action) -> Step {
+        Step {
+            at_ms,
+            proxy: "main".to_string(),
+            action,
+        }
+    }
+
+    fn add_latency(latency_ms: u64) -> Action {
+        Action::Add {
+            toxic: ToxicKind::Latency {
+                latency_ms,
+                jitter: Jitter::NONE,
+            }
+            .into(),
+        }
+    }
+
+    fn scenario(steps: Vec<Step>) -> Scenario {
+        Scenario {
+            name: "flaky".to_string(),
+            steps,
+        }
+    }
+
+    async fn toxic_names(proxies: &ProxyState) -> Vec<String> {
+        let proxies = proxies.lock().await;
+        let toxics = proxies["main"].toxics.borrow();
+        toxics.iter().map(|toxic| toxic.name.clone()).collect()
+    }
+
+    #[test]
+    fn example_scenario_parses() {
+        let scenario: Scenario = toml::from_str(include_str!("../config/scenario.toml")).unwrap();
+        assert_eq!(scenario.steps.len(), 3);
+        assert!(matches!(scenario.steps[1].action, Action::Add { .. }));
+        assert!(matches!(scenario.steps[2].action, Action::Reset));
+    }
+
+    #[test]
+    fn scenario_reads_json() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"name": "cut", "steps": [
+                {"at_ms": 0, "proxy": "main", "action": "remove", "toxic": "lag"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(&scenario.steps[0].action, Action::Remove { toxic } if toxic == "lag"));
+    }
+
+    #[tokio::test]
+    async fn scenario_runs_steps_on_schedule() {
+        let proxies = proxies("main");
+        let scenarios = Scenarios::default();
+        // Given out of order, run by time
+        let steps = vec![step(200, Action::Reset), step(0, add_latency(100))];
+
+        scenarios.start(scenario(steps), &proxies).await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(toxic_names(&proxies).await, ["latency_downstream"]);
+        assert_eq!(scenarios.list()[0].completed, 1);
+
+        time::sleep(Duration::from_millis(200)).await;
+        assert!(toxic_names(&proxies).await.is_empty());
+        assert!(scenarios.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scenario_updates_toxic_named_after_type_and_stream() {
+        let proxies = proxies("main");
+        let scenarios = Scenarios::default();
+        let update = Action::Update {
+            toxic: ToxicKind::Latency {
+                latency_ms: 500,
+                jitter: Jitter::NONE,
+            }
+            .into(),
+        };
+
+        let steps = vec![step(0, add_latency(100)), step(0, update)];
+        scenarios.start(scenario(steps), &proxies).await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+
+        let proxies = proxies.lock().await;
+        let toxic = proxies["main"].toxic("latency_downstream").unwrap();
+        assert!(matches!(
+            toxic.config().kind,
+            ToxicKind::Latency { latency_ms: 500, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn scenarios_reject_unknown_proxy_and_running_name() {
+        let proxies = proxies("main");
+        let scenarios = Scenarios::default();
+        let unknown = Step {
+            proxy: "missing".to_string(),
+            ..step(0, Action::Reset)
+        };
+
+        let error = scenarios.start(scenario(vec![unknown]), &proxies).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let later = vec![step(60_000, Action::Reset)];
+        scenarios.start(scenario(later.clone()), &proxies).await.unwrap();
+        let error = scenarios.start(scenario(later), &proxies).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[tokio::test]
+    async fn scenario_stop_skips_remaining_steps() {
+        let proxies = proxies("main");
+        let scenarios = Scenarios::default();
+        let steps = vec![step(0, add_latency(100)), step(100, Action::Reset)];
+        scenarios.start(scenario(steps), &proxies).await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+
+        assert!(scenarios.stop("flaky"));
+        assert!(!scenarios.stop("flaky"));
+        time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(toxic_names(&proxies).await, ["latency_downstream"]);
+    }
+}
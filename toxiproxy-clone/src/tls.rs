@@ -0,0 +1,293 @@
+use crate::net::{self, NetStream};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    self, ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+// TLS settings of a proxy. Without a certificate, TLS from clients passes through as
+// encrypted bytes, which stream toxics can delay or cut but not meaningfully change.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    // Certificate chain and private key, as PEM files, to terminate TLS from clients with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    // Connect to the upstream over TLS
+    #[serde(default)]
+    pub upstream: bool,
+    // Name the upstream certificate is checked against. Defaults to the upstream host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    // PEM file with the CA certificates to trust for the upstream, instead of the web PKI roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca: Option<PathBuf>,
+    // Accept any upstream certificate
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+fn read_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| {
+            invalid(format!(
+                "can't read certificates from {}: {}",
+                path.display(),
+                e
+            ))
+        })
+}
+
+// Host part of a `host:port` address, without the brackets of IPv6 addresses
+fn host(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+// A connection to either side of a proxy, with TLS or without
+pub(crate) trait Socket: AsyncRead + AsyncWrite + Unpin + Send {
+    // Makes closing the socket abort the connection with an RST rather than a FIN
+    fn set_zero_linger(&self) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn set_zero_linger(&self) -> io::Result<()> {
+        TcpStream::set_zero_linger(self)
+    }
+}
+
+impl Socket for NetStream {
+    fn set_zero_linger(&self) -> io::Result<()> {
+        NetStream::set_zero_linger(self)
+    }
+}
+
+impl<S: Socket> Socket for tokio_rustls::server::TlsStream<S> {
+    fn set_zero_linger(&self) -> io::Result<()> {
+        self.get_ref().0.set_zero_linger()
+    }
+}
+
+impl<S: Socket> Socket for tokio_rustls::client::TlsStream<S> {
+    fn set_zero_linger(&self) -> io::Result<()> {
+        self.get_ref().0.set_zero_linger()
+    }
+}
+
+// TLS of a running proxy, built from its settings when it starts
+#[derive(Default)]
+pub(crate) struct Tls {
+    acceptor: Option<TlsAcceptor>,
+    connector: Option<(TlsConnector, ServerName<'static>)>,
+}
+
+impl Tls {
+    // Reads the certificates and keys. Bad settings are reported as `InvalidInput`.
+    pub(crate) fn new(config: Option<&TlsConfig>, upstream: &str) -> io::Result<Tls> {
+        let Some(config) = config else {
+            return Ok(Tls::default());
+        };
+
+        let acceptor = match (&config.cert, &config.key) {
+            (Some(cert), Some(key)) => {
+                let certs = read_certs(cert)?;
+                let key = PrivateKeyDer::from_pem_file(key).map_err(|e| {
+                    invalid(format!(
+                        "can't read private key from {}: {}",
+                        key.display(),
+                        e
+                    ))
+                })?;
+                let server = ServerConfig::builder_with_provider(provider())
+                    .with_safe_default_protocol_versions()
+                    .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+                    .map_err(|e| invalid(e.to_string()))?;
+                Some(TlsAcceptor::from(Arc::new(server)))
+            }
+            (None, None) => None,
+            _ => return Err(invalid("cert and key must be given together".to_string())),
+        };
+
+        let connector = if config.upstream {
+            let builder = ClientConfig::builder_with_provider(provider())
+                .with_safe_default_protocol_versions()
+                .map_err(|e| invalid(e.to_string()))?;
+            let client = if config.insecure {
+                builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(NoVerification(provider())))
+                    .with_no_client_auth()
+            } else {
+                let mut roots = RootCertStore::empty();
+                match &config.ca {
+                    Some(ca) => {
+                        for cert in read_certs(ca)? {
+                            roots.add(cert).map_err(|e| invalid(e.to_string()))?;
+                        }
+                    }
+                    None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+                }
+                builder.with_root_certificates(roots).with_no_client_auth()
+            };
+
+            if config.server_name.is_none() && net::unix_path(upstream).is_some() {
+                return Err(invalid(
+                    "server_name is required for TLS to a Unix socket upstream".to_string(),
+                ));
+            }
+            let name = config.server_name.as_deref().unwrap_or(host(upstream));
+            let name = ServerName::try_from(name.to_string())
+                .map_err(|_| invalid(format!("invalid server name: {}", name)))?;
+            Some((TlsConnector::from(Arc::new(client)), name))
+        } else {
+            None
+        };
+
+        Ok(Tls {
+            acceptor,
+            connector,
+        })
+    }
+
+    // Runs the TLS handshake with the client, if the proxy terminates TLS
+    pub(crate) async fn accept<S>(&self, client: S) -> io::Result<Box<dyn Socket>>
+    where
+        S: Socket + 'static,
+    {
+        match &self.acceptor {
+            Some(acceptor) => Ok(Box::new(acceptor.accept(client).await?)),
+            None => Ok(Box::new(client)),
+        }
+    }
+
+    // Runs the TLS handshake with the upstream, if the proxy connects to it over TLS
+    pub(crate) async fn connect<S>(&self, upstream: S) -> io::Result<Box<dyn Socket>>
+    where
+        S: Socket + 'static,
+    {
+        match &self.connector {
+            Some((connector, name)) => {
+                Ok(Box::new(connector.connect(name.clone(), upstream).await?))
+            }
+            None => Ok(Box::new(upstream)),
+        }
+    }
+}
+
+// Accepts any upstream certificate, while still checking the handshake signatures
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_strips_port_and_brackets() {
+        assert_eq!(host("db.internal:5432"), "db.internal");
+        assert_eq!(host("[::1]:443"), "::1");
+    }
+
+    #[test]
+    fn tls_requires_cert_and_key_together() {
+        let config = TlsConfig {
+            cert: Some(PathBuf::from("testdata/localhost.pem")),
+            ..Default::default()
+        };
+        let error = Tls::new(Some(&config), "127.0.0.1:443").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn tls_requires_server_name_for_unix_upstream() {
+        let config = TlsConfig {
+            upstream: true,
+            ..Default::default()
+        };
+        let error = Tls::new(Some(&config), "unix:/run/app.sock").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let config = TlsConfig {
+            server_name: Some("app.internal".to_string()),
+            ..config
+        };
+        assert!(Tls::new(Some(&config), "unix:/run/app.sock").is_ok());
+    }
+
+    #[test]
+    fn tls_reports_missing_files() {
+        let config = TlsConfig {
+            cert: Some(PathBuf::from("testdata/missing.pem")),
+            key: Some(PathBuf::from("testdata/missing-key.pem")),
+            ..Default::default()
+        };
+        let error = Tls::new(Some(&config), "127.0.0.1:443").err().unwrap();
+        assert!(error.to_string().contains("testdata/missing.pem"));
+    }
+}
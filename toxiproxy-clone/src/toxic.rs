@@ -1,18 +1,573 @@
+use crate::http::{HttpRequest, HttpResponse};
+use crate::proxy::Protocol;
+use crate::toxics;
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
-// The Toxic trait defines behavior modifications for the proxy
+// The Toxic trait defines behavior modifications for the proxy. A toxic only sees the
+// direction of traffic its wrapper is attached to.
+#[async_trait]
 pub trait Toxic: Send + Sync {
-    fn modify_upstream(&self, data: &mut Vec<u8>);
-    fn modify_downstream(&self, data: &mut Vec<u8>);
+    // Random decisions come from the state's RNG, so a seed replays them
+    async fn modify(&self, _data: &mut Vec<u8>, _state: &mut ToxicState) {}
+
+    // Delivery hook, run after every toxic has modified the data. It decides how the data
+    // reaches the next link, so toxics can split it up or pace it rather than change it.
+    // Returning an error closes the connection, and `ConnectionReset` closes it with an RST.
+    async fn forward(
+        &self,
+        data: Vec<u8>,
+        _state: &mut ToxicState,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        next.forward(data).await
+    }
+
+    // Lifecycle hooks. `on_connect` runs once the upstream connection is established and before
+    // any data flows. `on_close` runs when the direction ends, before the close is passed on.
+    async fn on_connect(&self) {}
+    async fn on_close(&self) {}
+
+    // Resolves, with the error to close it with, when the toxic wants the connection closed. It
+    // is raced against the data flow while the toxic is active, and the default never resolves.
+    // `since` is when the toxic became active on the connection, so timers count from there
+    // however often the future is built again.
+    async fn until_close(&self, _since: Instant) -> io::Error {
+        future::pending().await
+    }
+
+    // Datagram hook, used instead of the stream hooks on UDP proxies. It returns the copies of
+    // the datagram to pass on, so a toxic can drop, duplicate or delay it.
+    fn datagram(&self, datagram: Datagram, _state: &mut ToxicState) -> Vec<Datagram> {
+        vec![datagram]
+    }
+
+    // HTTP hooks, run on HTTP proxies before the stream hooks. `request` runs on upstream toxics
+    // for every request, and answers it in place of the server by returning a response.
+    // `response` runs on downstream toxics for every response, along with its request, and
+    // replaces it by returning another. Bodies stream through, so both only see the head.
+    async fn request(&self, _request: &mut HttpRequest) -> Option<HttpResponse> {
+        None
+    }
+    async fn response(
+        &self,
+        _request: &HttpRequest,
+        _response: &mut HttpResponse,
+    ) -> Option<HttpResponse> {
+        None
+    }
+
+    // Whether the toxic can be used on proxies of the given protocol. HTTP proxies are TCP
+    // proxies underneath, so stream toxics work on both.
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol != Protocol::Udp
+    }
+
     fn get_type(&self) -> String;
-    fn get_config(&self) -> ToxicConfig;
+    fn get_kind(&self) -> ToxicKind;
+}
+
+// A datagram on its way through a UDP proxy, with the delay toxics have added to it so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct Datagram {
+    pub data: Vec<u8>,
+    pub delay: Duration,
+}
+
+// State the proxy keeps for every toxic on each connection
+#[derive(Debug)]
+pub struct ToxicState {
+    // Bytes the toxic has passed on so far
+    pub bytes: u64,
+    // Source of every random decision the toxic makes on the connection
+    pub rng: StdRng,
+    // Delay drawn once for the connection, by toxics that delay all of it by the same amount
+    pub delay: Option<Duration>,
+    // When the toxic became active on the connection, or was last updated
+    pub since: Instant,
+}
+
+impl ToxicState {
+    pub fn new(seed: u64) -> Self {
+        ToxicState {
+            bytes: 0,
+            rng: StdRng::seed_from_u64(seed),
+            delay: None,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Default for ToxicState {
+    fn default() -> Self {
+        ToxicState::new(rand::random())
+    }
+}
+
+// Seed toxics without one of their own derive theirs from, set once at startup
+static GLOBAL_SEED: OnceLock<u64> = OnceLock::new();
+
+pub fn set_global_seed(seed: u64) {
+    let _ = GLOBAL_SEED.set(seed);
+}
+
+// Derives a new seed from `seed` and `value` (SplitMix64), so related seeds don't give
+// related sequences
+fn mix(seed: u64, value: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(value.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Seed of a toxic without one in its config: derived from the global seed and its name when
+// there is one, random otherwise
+fn default_seed(name: &str) -> u64 {
+    match GLOBAL_SEED.get() {
+        Some(&seed) => name.bytes().fold(seed, |seed, byte| mix(seed, byte as u64)),
+        None => rand::random(),
+    }
+}
+
+// The next step for data leaving a toxic: either the next toxic or the destination socket
+#[async_trait]
+pub trait Link: Send {
+    async fn forward(&mut self, data: Vec<u8>) -> io::Result<()>;
+}
+
+// Direction of traffic a toxic applies to. Upstream is client to server, downstream is
+// server to client.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Upstream,
+    #[default]
+    Downstream,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Upstream => write!(f, "upstream"),
+            Stream::Downstream => write!(f, "downstream"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ToxicConfig {
+    // Unique within a proxy. Defaults to the toxic type and stream, e.g. `latency_downstream`.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub stream: Stream,
+    // Share of connections the toxic applies to, from 0.0 to 1.0
+    #[serde(default = "default_toxicity")]
+    pub toxicity: f64,
+    // Seed for the toxic's random decisions. Defaults to one derived from `--seed`, or a random
+    // one, and is reported back so a run can be replayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(flatten)]
+    pub kind: ToxicKind,
+}
+
+fn default_toxicity() -> f64 {
+    1.0
+}
+
+// A toxic on the downstream for every connection, named after its type
+impl From<ToxicKind> for ToxicConfig {
+    fn from(kind: ToxicKind) -> Self {
+        ToxicConfig {
+            name: String::new(),
+            stream: Stream::default(),
+            toxicity: default_toxicity(),
+            seed: None,
+            kind,
+        }
+    }
+}
+
+impl ToxicConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.toxicity) {
+            return Err(format!(
+                "toxicity must be between 0.0 and 1.0, got {}",
+                self.toxicity
+            ));
+        }
+        if let ToxicKind::Drop { probability }
+        | ToxicKind::Duplicate { probability }
+        | ToxicKind::Reorder { probability, .. }
+        | ToxicKind::Corrupt { probability } = self.kind
+        {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(format!(
+                    "probability must be between 0.0 and 1.0, got {}",
+                    probability
+                ));
+            }
+        }
+        if let ToxicKind::HttpStatus { status, .. } = self.kind {
+            if !(100..=599).contains(&status) {
+                return Err(format!("status must be between 100 and 599, got {}", status));
+            }
+        }
+        if let ToxicKind::Bandwidth { rate } = self.kind {
+            if rate.checked_mul(1000).is_none() {
+                return Err(format!("rate must be at most {} KB/s, got {}", u64::MAX / 1000, rate));
+            }
+        }
+        if let ToxicKind::Slicer { average_size, size_variation, .. } = self.kind {
+            if average_size.checked_add(size_variation).is_none() {
+                return Err(format!(
+                    "average_size plus size_variation must be at most {}, got {} and {}",
+                    usize::MAX,
+                    average_size,
+                    size_variation
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
-pub enum ToxicConfig {
-    Latency { latency_ms: u64 },
+pub enum ToxicKind {
+    Latency {
+        latency_ms: u64,
+        #[serde(flatten)]
+        jitter: Jitter,
+    },
     Corrupt { probability: f64 },
     SlowClose { delay_ms: u64 },
+    Bandwidth { rate: u64 },
+    Timeout { timeout_ms: u64 },
+    Slicer { average_size: usize, size_variation: usize, delay_us: u64 },
+    LimitData { bytes: u64 },
+    ResetPeer { timeout_ms: u64 },
+    Drop { probability: f64 },
+    Duplicate { probability: f64 },
+    Reorder { probability: f64, delay_ms: u64 },
+    HttpStatus {
+        status: u16,
+        #[serde(default)]
+        path_prefix: String,
+        #[serde(default)]
+        body: String,
+    },
+    HttpLatency {
+        latency_ms: u64,
+        #[serde(default)]
+        path_prefix: String,
+    },
+    StripHeaders {
+        headers: Vec<String>,
+        #[serde(default)]
+        path_prefix: String,
+    },
+}
+
+// How far and how the delay of a latency toxic strays from its `latency_ms`. Settings left at
+// their defaults are omitted, so plain latency toxics read as before.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jitter {
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub jitter_ms: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub distribution: Distribution,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub per: LatencyScope,
+}
+
+impl Jitter {
+    // A constant latency
+    pub const NONE: Jitter = Jitter {
+        jitter_ms: 0,
+        distribution: Distribution::Uniform,
+        per: LatencyScope::Chunk,
+    };
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+// Distribution latency is drawn from. `Uniform` spreads it evenly over `latency ± jitter`,
+// `Normal` centers it on `latency` with `jitter` as the standard deviation, and `Pareto` adds a
+// heavy-tailed delay averaging `jitter` on top of `latency`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    #[default]
+    Uniform,
+    Normal,
+    Pareto,
+}
+
+// Whether latency is drawn afresh for every chunk of data, or once for the whole connection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyScope {
+    #[default]
+    Chunk,
+    Connection,
+}
+
+// A toxic attached to a proxy, along with its name, the direction it applies to, its toxicity
+// and its seed
+pub struct ToxicWrapper {
+    pub name: String,
+    pub stream: Stream,
+    pub toxicity: f64,
+    pub seed: u64,
+    pub toxic: Box<dyn Toxic>,
+}
+
+impl ToxicWrapper {
+    pub fn new(config: ToxicConfig) -> Self {
+        let toxic = toxics::new_toxic(config.kind);
+        let name = if config.name.is_empty() {
+            format!("{}_{}", toxic.get_type(), config.stream)
+        } else {
+            config.name
+        };
+        let seed = config.seed.unwrap_or_else(|| default_seed(&name));
+        ToxicWrapper {
+            name,
+            stream: config.stream,
+            toxicity: config.toxicity,
+            seed,
+            toxic,
+        }
+    }
+
+    pub fn config(&self) -> ToxicConfig {
+        ToxicConfig {
+            name: self.name.clone(),
+            stream: self.stream,
+            toxicity: self.toxicity,
+            seed: Some(self.seed),
+            kind: self.toxic.get_kind(),
+        }
+    }
+
+    // Fresh state for the toxic on the proxy's `connection`th connection. Each connection gets
+    // its own RNG, so connections replay the same way whatever order their data comes in.
+    pub fn state(&self, connection: u64) -> ToxicState {
+        ToxicState::new(mix(self.seed, connection))
+    }
+
+    // Decides whether the toxic applies to a connection
+    pub fn roll(&self, rng: &mut StdRng) -> bool {
+        rng.random::<f64>() < self.toxicity
+    }
+}
+
+// Link that records every write along with when it happened
+#[cfg(test)]
+pub struct RecordingLink {
+    pub writes: Vec<(std::time::Instant, Vec<u8>)>,
+}
+
+#[cfg(test)]
+impl RecordingLink {
+    // Everything written, joined back together
+    pub fn data(&self) -> Vec<u8> {
+        self.writes.iter().flat_map(|(_, data)| data.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Link for RecordingLink {
+    async fn forward(&mut self, data: Vec<u8>) -> io::Result<()> {
+        self.writes.push((std::time::Instant::now(), data));
+        Ok(())
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toxic_config_defaults_to_downstream_for_every_connection() {
+        let config: ToxicConfig =
+            serde_json::from_str(r#"{"type": "Latency", "latency_ms": 100}"#).unwrap();
+        assert_eq!(config.stream, Stream::Downstream);
+        assert_eq!(config.toxicity, 1.0);
+    }
+
+    #[test]
+    fn toxic_config_reads_stream_and_toxicity() {
+        let config: ToxicConfig = serde_json::from_str(
+            r#"{"type": "Latency", "latency_ms": 100, "stream": "upstream", "toxicity": 0.3}"#,
+        )
+        .unwrap();
+        assert_eq!(config.stream, Stream::Upstream);
+        assert_eq!(config.toxicity, 0.3);
+        assert!(matches!(config.kind, ToxicKind::Latency { latency_ms: 100, .. }));
+    }
+
+    #[test]
+    fn toxic_config_reads_jitter() {
+        let config: ToxicConfig = serde_json::from_str(
+            r#"{"type": "Latency", "latency_ms": 100, "jitter_ms": 20, "distribution": "normal",
+                "per": "connection"}"#,
+        )
+        .unwrap();
+        let ToxicKind::Latency { jitter, .. } = config.kind else {
+            panic!("Expected ToxicKind::Latency");
+        };
+        assert_eq!(jitter.jitter_ms, 20);
+        assert_eq!(jitter.distribution, Distribution::Normal);
+        assert_eq!(jitter.per, LatencyScope::Connection);
+    }
+
+    #[test]
+    fn toxic_config_omits_jitter_left_at_defaults() {
+        let config: ToxicConfig = ToxicKind::Latency {
+            latency_ms: 100,
+            jitter: Jitter::NONE,
+        }
+        .into();
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["latency_ms"], 100);
+        assert!(json.get("jitter_ms").is_none());
+        assert!(json.get("distribution").is_none());
+    }
+
+    #[test]
+    fn toxic_config_rejects_toxicity_out_of_range() {
+        let config = ToxicConfig {
+            name: String::new(),
+            stream: Stream::Downstream,
+            toxicity: 1.5,
+            seed: None,
+            kind: ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE },
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn toxic_config_rejects_probability_out_of_range() {
+        for kind in [
+            ToxicKind::Drop { probability: 1.5 },
+            ToxicKind::Duplicate { probability: -0.1 },
+            ToxicKind::Reorder { probability: 2.0, delay_ms: 10 },
+            ToxicKind::Corrupt { probability: f64::NAN },
+        ] {
+            let config = ToxicConfig {
+                name: String::new(),
+                stream: Stream::Upstream,
+                toxicity: 1.0,
+                seed: None,
+                kind,
+            };
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn toxic_config_rejects_unknown_status() {
+        let config: ToxicConfig = ToxicKind::HttpStatus {
+            status: 1000,
+            path_prefix: String::new(),
+            body: String::new(),
+        }
+        .into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn toxic_config_rejects_rate_that_overflows() {
+        let config: ToxicConfig = ToxicKind::Bandwidth { rate: u64::MAX / 1000 + 1 }.into();
+        assert!(config.validate().is_err());
+        let config: ToxicConfig = ToxicKind::Bandwidth { rate: u64::MAX / 1000 }.into();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn toxic_config_rejects_slice_sizes_that_overflow() {
+        let config: ToxicConfig = ToxicKind::Slicer {
+            average_size: usize::MAX,
+            size_variation: 1,
+            delay_us: 0,
+        }
+        .into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn toxic_wrapper_names_toxic_after_type_and_stream() {
+        let config: ToxicConfig = serde_json::from_str(
+            r#"{"type": "SlowClose", "delay_ms": 100, "stream": "upstream"}"#,
+        )
+        .unwrap();
+        assert_eq!(ToxicWrapper::new(config).name, "slow_close_upstream");
+    }
+
+    #[test]
+    fn toxic_wrapper_keeps_given_name() {
+        let config: ToxicConfig =
+            serde_json::from_str(r#"{"name": "lag", "type": "Latency", "latency_ms": 100}"#)
+                .unwrap();
+        assert_eq!(ToxicWrapper::new(config).config().name, "lag");
+    }
+
+    #[test]
+    fn toxic_wrapper_rolls_by_toxicity() {
+        let config = |toxicity| ToxicConfig {
+            name: String::new(),
+            stream: Stream::Downstream,
+            toxicity,
+            seed: None,
+            kind: ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE },
+        };
+        let rng = &mut ToxicState::default().rng;
+        assert!(ToxicWrapper::new(config(1.0)).roll(rng));
+        assert!(!ToxicWrapper::new(config(0.0)).roll(rng));
+    }
+
+    #[test]
+    fn toxic_wrapper_reports_given_seed() {
+        let config: ToxicConfig =
+            serde_json::from_str(r#"{"type": "Latency", "latency_ms": 100, "seed": 42}"#).unwrap();
+        assert_eq!(ToxicWrapper::new(config).config().seed, Some(42));
+    }
+
+    #[test]
+    fn toxic_wrapper_replays_rolls_for_same_seed_and_connection() {
+        let config = ToxicConfig {
+            toxicity: 0.5,
+            seed: Some(7),
+            ..ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE }.into()
+        };
+        let toxic = ToxicWrapper::new(config);
+        let rolls = |connection| -> Vec<bool> {
+            let mut state = toxic.state(connection);
+            (0..64).map(|_| toxic.roll(&mut state.rng)).collect()
+        };
+        assert_eq!(rolls(3), rolls(3));
+        assert_ne!(rolls(3), rolls(4));
+    }
+
+    #[test]
+    fn mix_spreads_neighbouring_values() {
+        assert_eq!(mix(1, 2), mix(1, 2));
+        assert_ne!(mix(1, 2), mix(1, 3));
+        assert_ne!(mix(1, 2), mix(2, 1));
+    }
+}
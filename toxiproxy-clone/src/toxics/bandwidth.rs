@@ -0,0 +1,105 @@
+use crate::toxic::{Link, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use std::io;
+use std::time::Duration;
+use tokio::time;
+
+// Bandwidth toxic limits throughput to `rate` KB/s. A rate of 0 leaves the connection unthrottled.
+pub struct BandwidthToxic {
+    pub rate: u64,
+}
+
+impl BandwidthToxic {
+    async fn throttle(&self, mut data: Vec<u8>, next: &mut dyn Link) -> io::Result<()> {
+        if self.rate == 0 {
+            return next.forward(data).await;
+        }
+
+        // Deliver at most 100ms worth of data at a time so slow links trickle instead of bursting
+        let bytes_per_second = self.rate * 1000;
+        let piece_size = (bytes_per_second / 10).max(1) as usize;
+        while !data.is_empty() {
+            let rest = data.split_off(data.len().min(piece_size));
+            time::sleep(Duration::from_secs_f64(
+                data.len() as f64 / bytes_per_second as f64,
+            ))
+            .await;
+            next.forward(data).await?;
+            data = rest;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Toxic for BandwidthToxic {
+    async fn forward(
+        &self,
+        data: Vec<u8>,
+        _state: &mut ToxicState,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        self.throttle(data, next).await
+    }
+
+    fn get_type(&self) -> String {
+        "bandwidth".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Bandwidth { rate: self.rate }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxic::RecordingLink;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn bandwidth_toxic_limits_rate() {
+        let toxic = BandwidthToxic { rate: 10 };
+        let mut link = RecordingLink { writes: Vec::new() };
+        let start = Instant::now();
+        toxic.forward(vec![0; 2000], &mut ToxicState::default(), &mut link).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(link.writes.iter().map(|(_, data)| data.len()).sum::<usize>(), 2000);
+    }
+
+    #[tokio::test]
+    async fn bandwidth_toxic_splits_data_into_pieces() {
+        let toxic = BandwidthToxic { rate: 10 };
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![0; 2500], &mut ToxicState::default(), &mut link).await.unwrap();
+        let sizes: Vec<usize> = link.writes.iter().map(|(_, data)| data.len()).collect();
+        assert_eq!(sizes, vec![1000, 1000, 500]);
+    }
+
+    #[tokio::test]
+    async fn bandwidth_toxic_with_zero_rate_does_not_throttle() {
+        let toxic = BandwidthToxic { rate: 0 };
+        let mut link = RecordingLink { writes: Vec::new() };
+        let start = Instant::now();
+        toxic.forward(vec![0; 100_000], &mut ToxicState::default(), &mut link).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(link.writes.len(), 1);
+    }
+
+    #[test]
+    fn bandwidth_toxic_get_type_returns_correct_type() {
+        let toxic = BandwidthToxic { rate: 100 };
+        assert_eq!(toxic.get_type(), "bandwidth");
+    }
+
+    #[test]
+    fn bandwidth_toxic_get_kind_returns_correct_kind() {
+        let toxic = BandwidthToxic { rate: 100 };
+        if let ToxicKind::Bandwidth { rate } = toxic.get_kind() {
+            assert_eq!(rate, 100);
+        } else {
+            panic!("Expected ToxicKind::Bandwidth");
+        }
+    }
+}
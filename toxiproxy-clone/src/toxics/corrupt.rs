@@ -1,30 +1,44 @@
-use crate::toxic::{Toxic, ToxicConfig};
+use crate::proxy::Protocol;
+use crate::toxic::{Datagram, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
 use rand::Rng;
 
 pub struct CorruptToxic {
     pub probability: f64,
 }
 
-impl Toxic for CorruptToxic {
-    fn modify_upstream(&self, data: &mut Vec<u8>) {
-        let mut rng = rand::thread_rng();
-        if rng.gen_bool(self.probability) {
+impl CorruptToxic {
+    // Flips random bits of the first byte, so a corrupted byte always differs from the original
+    fn corrupt(&self, data: &mut [u8], state: &mut ToxicState) {
+        if state.rng.random_bool(self.probability) {
             if let Some(byte) = data.get_mut(0) {
-                *byte = rng.random();
+                *byte ^= state.rng.random_range(1..=u8::MAX);
             }
         }
     }
+}
+
+#[async_trait]
+impl Toxic for CorruptToxic {
+    async fn modify(&self, data: &mut Vec<u8>, state: &mut ToxicState) {
+        self.corrupt(data, state);
+    }
+
+    fn datagram(&self, mut datagram: Datagram, state: &mut ToxicState) -> Vec<Datagram> {
+        self.corrupt(&mut datagram.data, state);
+        vec![datagram]
+    }
 
-    fn modify_downstream(&self, data: &mut Vec<u8>) {
-        self.modify_upstream(data);
+    fn supports(&self, _protocol: Protocol) -> bool {
+        true
     }
 
     fn get_type(&self) -> String {
         "corrupt".to_string()
     }
 
-    fn get_config(&self) -> ToxicConfig {
-        ToxicConfig::Corrupt {
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Corrupt {
             probability: self.probability,
         }
     }
@@ -33,29 +47,51 @@ impl Toxic for CorruptToxic {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
-    #[test]
-    fn corrupt_toxic_modifies_upstream_with_probability() {
+    #[tokio::test]
+    async fn corrupt_toxic_modifies_with_probability() {
         let mut data = vec![1, 2, 3, 4];
         let toxic = CorruptToxic { probability: 1.0 };
-        toxic.modify_upstream(&mut data);
+        toxic.modify(&mut data, &mut ToxicState::default()).await;
         assert_ne!(data[0], 1);
     }
 
-    #[test]
-    fn corrupt_toxic_does_not_modify_upstream_with_zero_probability() {
+    #[tokio::test]
+    async fn corrupt_toxic_does_not_modify_with_zero_probability() {
         let mut data = vec![1, 2, 3, 4];
         let toxic = CorruptToxic { probability: 0.0 };
-        toxic.modify_upstream(&mut data);
+        toxic.modify(&mut data, &mut ToxicState::default()).await;
         assert_eq!(data[0], 1);
     }
 
     #[test]
-    fn corrupt_toxic_modifies_downstream() {
-        let mut data = vec![1, 2, 3, 4];
+    fn corrupt_toxic_replays_corruption_for_same_seed() {
+        let toxic = CorruptToxic { probability: 0.5 };
+        let run = |seed| {
+            let mut state = ToxicState::new(seed);
+            (0..64)
+                .map(|_| {
+                    let mut data = vec![0];
+                    toxic.corrupt(&mut data, &mut state);
+                    data[0]
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn corrupt_toxic_corrupts_datagrams() {
         let toxic = CorruptToxic { probability: 1.0 };
-        toxic.modify_downstream(&mut data);
-        assert_ne!(data[0], 1);
+        let datagram = Datagram {
+            data: vec![1, 2, 3, 4],
+            delay: Duration::ZERO,
+        };
+        let corrupted = toxic.datagram(datagram, &mut ToxicState::default());
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!(corrupted[0].data[1..], [2, 3, 4]);
     }
 
     #[test]
@@ -65,12 +101,12 @@ mod tests {
     }
 
     #[test]
-    fn corrupt_toxic_get_config_returns_correct_config() {
+    fn corrupt_toxic_get_kind_returns_correct_kind() {
         let toxic = CorruptToxic { probability: 0.5 };
-        if let ToxicConfig::Corrupt { probability } = toxic.get_config() {
+        if let ToxicKind::Corrupt { probability } = toxic.get_kind() {
             assert_eq!(probability, 0.5);
         } else {
-            panic!("Expected ToxicConfig::Corrupt");
+            panic!("Expected ToxicKind::Corrupt");
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use crate::proxy::Protocol;
+use crate::toxic::{Datagram, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use rand::Rng;
+
+// Drop toxic loses datagrams with the given probability
+pub struct DropToxic {
+    pub probability: f64,
+}
+
+#[async_trait]
+impl Toxic for DropToxic {
+    fn datagram(&self, datagram: Datagram, state: &mut ToxicState) -> Vec<Datagram> {
+        if state.rng.random_bool(self.probability) {
+            return Vec::new();
+        }
+        vec![datagram]
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Udp
+    }
+
+    fn get_type(&self) -> String {
+        "drop".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Drop {
+            probability: self.probability,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn datagram() -> Datagram {
+        Datagram {
+            data: vec![1, 2, 3, 4],
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn drop_toxic_drops_with_probability() {
+        let toxic = DropToxic { probability: 1.0 };
+        assert!(toxic.datagram(datagram(), &mut ToxicState::default()).is_empty());
+    }
+
+    #[test]
+    fn drop_toxic_keeps_with_zero_probability() {
+        let toxic = DropToxic { probability: 0.0 };
+        assert_eq!(toxic.datagram(datagram(), &mut ToxicState::default()), vec![datagram()]);
+    }
+
+    #[test]
+    fn drop_toxic_only_supports_udp() {
+        let toxic = DropToxic { probability: 0.5 };
+        assert!(toxic.supports(Protocol::Udp));
+        assert!(!toxic.supports(Protocol::Tcp));
+    }
+
+    #[test]
+    fn drop_toxic_get_type_returns_correct_type() {
+        let toxic = DropToxic { probability: 0.5 };
+        assert_eq!(toxic.get_type(), "drop");
+    }
+
+    #[test]
+    fn drop_toxic_get_kind_returns_correct_kind() {
+        let toxic = DropToxic { probability: 0.5 };
+        if let ToxicKind::Drop { probability } = toxic.get_kind() {
+            assert_eq!(probability, 0.5);
+        } else {
+            panic!("Expected ToxicKind::Drop");
+        }
+    }
+}
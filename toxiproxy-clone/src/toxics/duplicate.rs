@@ -0,0 +1,75 @@
+use crate::proxy::Protocol;
+use crate::toxic::{Datagram, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use rand::Rng;
+
+// Duplicate toxic sends datagrams twice with the given probability
+pub struct DuplicateToxic {
+    pub probability: f64,
+}
+
+#[async_trait]
+impl Toxic for DuplicateToxic {
+    fn datagram(&self, datagram: Datagram, state: &mut ToxicState) -> Vec<Datagram> {
+        if state.rng.random_bool(self.probability) {
+            return vec![datagram.clone(), datagram];
+        }
+        vec![datagram]
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Udp
+    }
+
+    fn get_type(&self) -> String {
+        "duplicate".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Duplicate {
+            probability: self.probability,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn datagram() -> Datagram {
+        Datagram {
+            data: vec![1, 2, 3, 4],
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn duplicate_toxic_duplicates_with_probability() {
+        let toxic = DuplicateToxic { probability: 1.0 };
+        let datagrams = toxic.datagram(datagram(), &mut ToxicState::default());
+        assert_eq!(datagrams, vec![datagram(), datagram()]);
+    }
+
+    #[test]
+    fn duplicate_toxic_passes_once_with_zero_probability() {
+        let toxic = DuplicateToxic { probability: 0.0 };
+        assert_eq!(toxic.datagram(datagram(), &mut ToxicState::default()), vec![datagram()]);
+    }
+
+    #[test]
+    fn duplicate_toxic_get_type_returns_correct_type() {
+        let toxic = DuplicateToxic { probability: 0.5 };
+        assert_eq!(toxic.get_type(), "duplicate");
+    }
+
+    #[test]
+    fn duplicate_toxic_get_kind_returns_correct_kind() {
+        let toxic = DuplicateToxic { probability: 0.5 };
+        if let ToxicKind::Duplicate { probability } = toxic.get_kind() {
+            assert_eq!(probability, 0.5);
+        } else {
+            panic!("Expected ToxicKind::Duplicate");
+        }
+    }
+}
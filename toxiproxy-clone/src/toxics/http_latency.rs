@@ -0,0 +1,105 @@
+use crate::http::{HttpRequest, HttpResponse};
+use crate::proxy::Protocol;
+use crate::toxic::{Toxic, ToxicKind};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::time;
+
+// HTTP latency toxic delays requests, or their responses downstream, on matching paths only
+pub struct HttpLatencyToxic {
+    pub latency: Duration,
+    pub path_prefix: String,
+}
+
+#[async_trait]
+impl Toxic for HttpLatencyToxic {
+    async fn request(&self, request: &mut HttpRequest) -> Option<HttpResponse> {
+        if request.matches(&self.path_prefix) {
+            time::sleep(self.latency).await;
+        }
+        None
+    }
+
+    async fn response(
+        &self,
+        request: &HttpRequest,
+        _response: &mut HttpResponse,
+    ) -> Option<HttpResponse> {
+        if request.matches(&self.path_prefix) {
+            time::sleep(self.latency).await;
+        }
+        None
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Http
+    }
+
+    fn get_type(&self) -> String {
+        "http_latency".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::HttpLatency {
+            latency_ms: self.latency.as_millis() as u64,
+            path_prefix: self.path_prefix.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn request(path: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            version: 1,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn toxic() -> HttpLatencyToxic {
+        HttpLatencyToxic {
+            latency: Duration::from_millis(100),
+            path_prefix: "/slow".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn http_latency_toxic_delays_matching_requests() {
+        let start = Instant::now();
+        assert_eq!(toxic().request(&mut request("/slow/report")).await, None);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn http_latency_toxic_skips_other_paths() {
+        let start = Instant::now();
+        let mut response = HttpResponse::new(200, "");
+        toxic().response(&request("/fast"), &mut response).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn http_latency_toxic_get_type_returns_correct_type() {
+        assert_eq!(toxic().get_type(), "http_latency");
+    }
+
+    #[test]
+    fn http_latency_toxic_get_kind_returns_correct_kind() {
+        if let ToxicKind::HttpLatency {
+            latency_ms,
+            path_prefix,
+        } = toxic().get_kind()
+        {
+            assert_eq!(latency_ms, 100);
+            assert_eq!(path_prefix, "/slow");
+        } else {
+            panic!("Expected ToxicKind::HttpLatency");
+        }
+    }
+}
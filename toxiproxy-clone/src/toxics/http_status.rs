@@ -0,0 +1,110 @@
+use crate::http::{HttpRequest, HttpResponse};
+use crate::proxy::Protocol;
+use crate::toxic::{Toxic, ToxicKind};
+use async_trait::async_trait;
+
+// HTTP status toxic answers matching requests with an injected status, such as 503 or 429.
+// Upstream it answers in place of the server, downstream it replaces the server's response.
+pub struct HttpStatusToxic {
+    pub status: u16,
+    pub path_prefix: String,
+    pub body: String,
+}
+
+#[async_trait]
+impl Toxic for HttpStatusToxic {
+    async fn request(&self, request: &mut HttpRequest) -> Option<HttpResponse> {
+        request
+            .matches(&self.path_prefix)
+            .then(|| HttpResponse::new(self.status, &self.body))
+    }
+
+    async fn response(
+        &self,
+        request: &HttpRequest,
+        _response: &mut HttpResponse,
+    ) -> Option<HttpResponse> {
+        request
+            .matches(&self.path_prefix)
+            .then(|| HttpResponse::new(self.status, &self.body))
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Http
+    }
+
+    fn get_type(&self) -> String {
+        "http_status".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::HttpStatus {
+            status: self.status,
+            path_prefix: self.path_prefix.clone(),
+            body: self.body.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            version: 1,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn toxic() -> HttpStatusToxic {
+        HttpStatusToxic {
+            status: 503,
+            path_prefix: "/api".to_string(),
+            body: "unavailable".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn http_status_toxic_answers_matching_requests() {
+        let response = toxic().request(&mut request("/api/orders")).await.unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(response.body, b"unavailable");
+    }
+
+    #[tokio::test]
+    async fn http_status_toxic_ignores_other_paths() {
+        assert_eq!(toxic().request(&mut request("/health")).await, None);
+    }
+
+    #[tokio::test]
+    async fn http_status_toxic_replaces_responses() {
+        let mut response = HttpResponse::new(200, "ok");
+        let replaced = toxic().response(&request("/api"), &mut response).await;
+        assert_eq!(replaced.unwrap().status, 503);
+        assert_eq!(toxic().response(&request("/health"), &mut response).await, None);
+    }
+
+    #[test]
+    fn http_status_toxic_get_type_returns_correct_type() {
+        assert_eq!(toxic().get_type(), "http_status");
+    }
+
+    #[test]
+    fn http_status_toxic_get_kind_returns_correct_kind() {
+        if let ToxicKind::HttpStatus {
+            status,
+            path_prefix,
+            ..
+        } = toxic().get_kind()
+        {
+            assert_eq!(status, 503);
+            assert_eq!(path_prefix, "/api");
+        } else {
+            panic!("Expected ToxicKind::HttpStatus");
+        }
+    }
+}
@@ -1,28 +1,87 @@
-use crate::toxic::{Toxic, ToxicConfig};
-use std::thread;
+use crate::proxy::Protocol;
+use crate::toxic::{Datagram, Distribution, Jitter, LatencyScope, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand_distr::{Normal, Pareto};
 use std::time::Duration;
+use tokio::time;
 
-// Latency toxic adds delay to the connection
+// Pareto shape. At 2 the tail is heavy enough that p99 is ten times the jitter above `latency`.
+const PARETO_SHAPE: f64 = 2.0;
+// Cap on the delay a single draw adds, in multiples of the jitter, so one unlucky draw can't
+// stall a connection for good
+const MAX_JITTER_FACTOR: f64 = 1000.0;
+
+// Latency toxic adds delay to the connection, varied by `jitter` following `distribution`
 pub struct LatencyToxic {
     pub latency: Duration,
+    pub jitter: Duration,
+    pub distribution: Distribution,
+    pub per: LatencyScope,
+}
+
+impl LatencyToxic {
+    // Draws a delay from the distribution
+    fn draw(&self, rng: &mut StdRng) -> Duration {
+        if self.jitter.is_zero() {
+            return self.latency;
+        }
+        let latency = self.latency.as_secs_f64();
+        let jitter = self.jitter.as_secs_f64();
+        let delay = match self.distribution {
+            Distribution::Uniform => latency + rng.random_range(-jitter..=jitter),
+            Distribution::Normal => {
+                rng.sample(Normal::new(latency, jitter).expect("jitter is positive"))
+            }
+            Distribution::Pareto => {
+                let pareto = Pareto::new(jitter, PARETO_SHAPE).expect("jitter is positive");
+                latency + rng.sample(pareto) - jitter
+            }
+        };
+        let max = latency + jitter * MAX_JITTER_FACTOR;
+        Duration::from_secs_f64(delay.clamp(0.0, max))
+    }
+
+    // Delay for the next chunk or datagram of a connection
+    fn delay(&self, state: &mut ToxicState) -> Duration {
+        match self.per {
+            LatencyScope::Chunk => self.draw(&mut state.rng),
+            LatencyScope::Connection => match state.delay {
+                Some(delay) => delay,
+                None => *state.delay.insert(self.draw(&mut state.rng)),
+            },
+        }
+    }
 }
 
+#[async_trait]
 impl Toxic for LatencyToxic {
-    fn modify_upstream(&self, _data: &mut Vec<u8>) {
-        thread::sleep(self.latency);
+    async fn modify(&self, _data: &mut Vec<u8>, state: &mut ToxicState) {
+        time::sleep(self.delay(state)).await;
+    }
+
+    fn datagram(&self, mut datagram: Datagram, state: &mut ToxicState) -> Vec<Datagram> {
+        datagram.delay += self.delay(state);
+        vec![datagram]
     }
 
-    fn modify_downstream(&self, _data: &mut Vec<u8>) {
-        thread::sleep(self.latency);
+    fn supports(&self, _protocol: Protocol) -> bool {
+        true
     }
 
     fn get_type(&self) -> String {
         "latency".to_string()
     }
 
-    fn get_config(&self) -> ToxicConfig {
-        ToxicConfig::Latency {
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Latency {
             latency_ms: self.latency.as_millis() as u64,
+            jitter: Jitter {
+                jitter_ms: self.jitter.as_millis() as u64,
+                distribution: self.distribution,
+                per: self.per,
+            },
         }
     }
 }
@@ -32,39 +91,118 @@ mod tests {
     use super::*;
     use std::time::Duration;
 
-    #[test]
-    fn latency_toxic_adds_delay_upstream() {
+    fn latency(latency_ms: u64) -> LatencyToxic {
+        jittered(latency_ms, 0, Distribution::Uniform, LatencyScope::Chunk)
+    }
+
+    fn jittered(
+        latency_ms: u64,
+        jitter_ms: u64,
+        distribution: Distribution,
+        per: LatencyScope,
+    ) -> LatencyToxic {
+        LatencyToxic {
+            latency: Duration::from_millis(latency_ms),
+            jitter: Duration::from_millis(jitter_ms),
+            distribution,
+            per,
+        }
+    }
+
+    // Delays drawn for `count` chunks of one connection
+    fn draws(toxic: &LatencyToxic, count: usize) -> Vec<Duration> {
+        let mut state = ToxicState::new(42);
+        (0..count).map(|_| toxic.delay(&mut state)).collect()
+    }
+
+    #[tokio::test]
+    async fn latency_toxic_adds_delay() {
         let mut data = vec![1, 2, 3, 4];
-        let toxic = LatencyToxic { latency: Duration::from_millis(100) };
+        let toxic = latency(100);
         let start = std::time::Instant::now();
-        toxic.modify_upstream(&mut data);
+        toxic.modify(&mut data, &mut ToxicState::default()).await;
         let elapsed = start.elapsed();
         assert!(elapsed >= Duration::from_millis(100));
+        assert_eq!(data, vec![1, 2, 3, 4]);
     }
 
     #[test]
-    fn latency_toxic_adds_delay_downstream() {
-        let mut data = vec![1, 2, 3, 4];
-        let toxic = LatencyToxic { latency: Duration::from_millis(100) };
-        let start = std::time::Instant::now();
-        toxic.modify_downstream(&mut data);
-        let elapsed = start.elapsed();
-        assert!(elapsed >= Duration::from_millis(100));
+    fn latency_toxic_delays_datagrams() {
+        let toxic = latency(100);
+        let datagram = Datagram {
+            data: vec![1, 2, 3, 4],
+            delay: Duration::from_millis(10),
+        };
+        let datagrams = toxic.datagram(datagram, &mut ToxicState::default());
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].delay, Duration::from_millis(110));
+    }
+
+    #[test]
+    fn latency_toxic_spreads_uniform_jitter_around_latency() {
+        let toxic = jittered(100, 20, Distribution::Uniform, LatencyScope::Chunk);
+        let delays = draws(&toxic, 1000);
+        let range = Duration::from_millis(80)..=Duration::from_millis(120);
+        assert!(delays.iter().all(|delay| range.contains(delay)));
+        assert!(delays
+            .iter()
+            .any(|delay| *delay < Duration::from_millis(90)));
+        assert!(delays
+            .iter()
+            .any(|delay| *delay > Duration::from_millis(110)));
+    }
+
+    #[test]
+    fn latency_toxic_centers_normal_jitter_on_latency() {
+        let toxic = jittered(100, 10, Distribution::Normal, LatencyScope::Chunk);
+        let delays = draws(&toxic, 1000);
+        let mean = delays.iter().sum::<Duration>() / 1000;
+        assert!(mean > Duration::from_millis(98) && mean < Duration::from_millis(102));
+        assert!(delays
+            .iter()
+            .any(|delay| *delay > Duration::from_millis(120)));
+    }
+
+    #[test]
+    fn latency_toxic_gives_pareto_jitter_a_long_tail() {
+        let toxic = jittered(100, 10, Distribution::Pareto, LatencyScope::Chunk);
+        let mut delays = draws(&toxic, 1000);
+        delays.sort();
+        assert!(delays[0] >= Duration::from_millis(100));
+        // Half the draws stay within a few milliseconds, while p99 is around 90ms above latency
+        assert!(delays[500] < Duration::from_millis(110));
+        assert!(delays[990] > Duration::from_millis(150));
+    }
+
+    #[test]
+    fn latency_toxic_draws_once_per_connection() {
+        let toxic = jittered(100, 50, Distribution::Uniform, LatencyScope::Connection);
+        let delays = draws(&toxic, 10);
+        assert!(delays.iter().all(|delay| *delay == delays[0]));
+    }
+
+    #[test]
+    fn latency_toxic_replays_jitter_for_same_seed() {
+        let toxic = jittered(100, 50, Distribution::Normal, LatencyScope::Chunk);
+        assert_eq!(draws(&toxic, 100), draws(&toxic, 100));
     }
 
     #[test]
     fn latency_toxic_get_type_returns_correct_type() {
-        let toxic = LatencyToxic { latency: Duration::from_millis(100) };
+        let toxic = latency(100);
         assert_eq!(toxic.get_type(), "latency");
     }
 
     #[test]
-    fn latency_toxic_get_config_returns_correct_config() {
-        let toxic = LatencyToxic { latency: Duration::from_millis(100) };
-        if let ToxicConfig::Latency { latency_ms } = toxic.get_config() {
+    fn latency_toxic_get_kind_returns_correct_kind() {
+        let toxic = jittered(100, 20, Distribution::Pareto, LatencyScope::Connection);
+        if let ToxicKind::Latency { latency_ms, jitter } = toxic.get_kind() {
             assert_eq!(latency_ms, 100);
+            assert_eq!(jitter.jitter_ms, 20);
+            assert_eq!(jitter.distribution, Distribution::Pareto);
+            assert_eq!(jitter.per, LatencyScope::Connection);
         } else {
-            panic!("Expected ToxicConfig::Latency");
+            panic!("Expected ToxicKind::Latency");
         }
     }
-}
\ No newline at end of file
+}
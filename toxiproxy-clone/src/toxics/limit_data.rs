@@ -0,0 +1,103 @@
+use crate::toxic::{Link, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use std::io;
+
+// Limit data toxic closes the connection once `bytes` have been passed on in a direction,
+// truncating the chunk that crosses the limit
+pub struct LimitDataToxic {
+    pub bytes: u64,
+}
+
+impl LimitDataToxic {
+    async fn limit(
+        &self,
+        mut data: Vec<u8>,
+        state: &mut ToxicState,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        let remaining = self.bytes.saturating_sub(state.bytes);
+        if (data.len() as u64) < remaining {
+            state.bytes += data.len() as u64;
+            return next.forward(data).await;
+        }
+
+        data.truncate(remaining as usize);
+        state.bytes += data.len() as u64;
+        if !data.is_empty() {
+            next.forward(data).await?;
+        }
+        Err(io::Error::new(io::ErrorKind::ConnectionAborted, "data limit reached"))
+    }
+}
+
+#[async_trait]
+impl Toxic for LimitDataToxic {
+    async fn forward(
+        &self,
+        data: Vec<u8>,
+        state: &mut ToxicState,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        self.limit(data, state, next).await
+    }
+
+    fn get_type(&self) -> String {
+        "limit_data".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::LimitData { bytes: self.bytes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxic::RecordingLink;
+
+    #[tokio::test]
+    async fn limit_data_toxic_forwards_data_below_limit() {
+        let toxic = LimitDataToxic { bytes: 10 };
+        let mut state = ToxicState::default();
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut state, &mut link).await.unwrap();
+        assert_eq!(link.data(), vec![1, 2, 3, 4]);
+        assert_eq!(state.bytes, 4);
+    }
+
+    #[tokio::test]
+    async fn limit_data_toxic_truncates_data_at_limit_and_closes() {
+        let toxic = LimitDataToxic { bytes: 6 };
+        let mut state = ToxicState::default();
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut state, &mut link).await.unwrap();
+        let result = toxic.forward(vec![5, 6, 7, 8], &mut state, &mut link).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+        assert_eq!(link.data(), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn limit_data_toxic_with_zero_bytes_closes_immediately() {
+        let toxic = LimitDataToxic { bytes: 0 };
+        let mut link = RecordingLink { writes: Vec::new() };
+        let result = toxic.forward(vec![1], &mut ToxicState::default(), &mut link).await;
+        assert!(result.is_err());
+        assert!(link.writes.is_empty());
+    }
+
+    #[test]
+    fn limit_data_toxic_get_type_returns_correct_type() {
+        let toxic = LimitDataToxic { bytes: 100 };
+        assert_eq!(toxic.get_type(), "limit_data");
+    }
+
+    #[test]
+    fn limit_data_toxic_get_kind_returns_correct_kind() {
+        let toxic = LimitDataToxic { bytes: 100 };
+        if let ToxicKind::LimitData { bytes } = toxic.get_kind() {
+            assert_eq!(bytes, 100);
+        } else {
+            panic!("Expected ToxicKind::LimitData");
+        }
+    }
+}
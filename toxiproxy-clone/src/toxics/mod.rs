@@ -1,3 +1,96 @@
+pub mod bandwidth;
 pub mod corrupt;
+pub mod drop;
+pub mod duplicate;
+pub mod http_latency;
+pub mod http_status;
 pub mod latency;
-pub mod slow_close;
\ No newline at end of file
+pub mod limit_data;
+pub mod reorder;
+pub mod reset_peer;
+pub mod slicer;
+pub mod slow_close;
+pub mod strip_headers;
+pub mod timeout;
+
+use crate::toxic::{Toxic, ToxicKind};
+use bandwidth::BandwidthToxic;
+use corrupt::CorruptToxic;
+use drop::DropToxic;
+use duplicate::DuplicateToxic;
+use http_latency::HttpLatencyToxic;
+use http_status::HttpStatusToxic;
+use latency::LatencyToxic;
+use limit_data::LimitDataToxic;
+use reorder::ReorderToxic;
+use reset_peer::ResetPeerToxic;
+use slicer::SlicerToxic;
+use slow_close::SlowCloseToxic;
+use std::time::Duration;
+use strip_headers::StripHeadersToxic;
+use timeout::TimeoutToxic;
+
+// Builds the toxic described by `kind`
+pub fn new_toxic(kind: ToxicKind) -> Box<dyn Toxic> {
+    match kind {
+        ToxicKind::Latency { latency_ms, jitter } => Box::new(LatencyToxic {
+            latency: Duration::from_millis(latency_ms),
+            jitter: Duration::from_millis(jitter.jitter_ms),
+            distribution: jitter.distribution,
+            per: jitter.per,
+        }),
+        ToxicKind::Corrupt { probability } => Box::new(CorruptToxic { probability }),
+        ToxicKind::SlowClose { delay_ms } => Box::new(SlowCloseToxic {
+            delay: Duration::from_millis(delay_ms),
+        }),
+        ToxicKind::Bandwidth { rate } => Box::new(BandwidthToxic { rate }),
+        ToxicKind::Timeout { timeout_ms } => Box::new(TimeoutToxic {
+            timeout: Duration::from_millis(timeout_ms),
+        }),
+        ToxicKind::Slicer {
+            average_size,
+            size_variation,
+            delay_us,
+        } => Box::new(SlicerToxic {
+            average_size,
+            size_variation,
+            delay: Duration::from_micros(delay_us),
+        }),
+        ToxicKind::LimitData { bytes } => Box::new(LimitDataToxic { bytes }),
+        ToxicKind::ResetPeer { timeout_ms } => Box::new(ResetPeerToxic {
+            timeout: Duration::from_millis(timeout_ms),
+        }),
+        ToxicKind::Drop { probability } => Box::new(DropToxic { probability }),
+        ToxicKind::Duplicate { probability } => Box::new(DuplicateToxic { probability }),
+        ToxicKind::Reorder {
+            probability,
+            delay_ms,
+        } => Box::new(ReorderToxic {
+            probability,
+            delay: Duration::from_millis(delay_ms),
+        }),
+        ToxicKind::HttpStatus {
+            status,
+            path_prefix,
+            body,
+        } => Box::new(HttpStatusToxic {
+            status,
+            path_prefix,
+            body,
+        }),
+        ToxicKind::HttpLatency {
+            latency_ms,
+            path_prefix,
+        } => Box::new(HttpLatencyToxic {
+            latency: Duration::from_millis(latency_ms),
+            path_prefix,
+        }),
+        ToxicKind::StripHeaders {
+            headers,
+            path_prefix,
+        } => Box::new(StripHeadersToxic {
+            headers,
+            path_prefix,
+        }),
+    }
+}
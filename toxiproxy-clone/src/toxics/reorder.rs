@@ -0,0 +1,96 @@
+use crate::proxy::Protocol;
+use crate::toxic::{Datagram, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use rand::Rng;
+use std::time::Duration;
+
+// Reorder toxic holds datagrams back by `delay` with the given probability, so the datagrams
+// after them overtake them
+pub struct ReorderToxic {
+    pub probability: f64,
+    pub delay: Duration,
+}
+
+#[async_trait]
+impl Toxic for ReorderToxic {
+    fn datagram(&self, mut datagram: Datagram, state: &mut ToxicState) -> Vec<Datagram> {
+        if state.rng.random_bool(self.probability) {
+            datagram.delay += self.delay;
+        }
+        vec![datagram]
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Udp
+    }
+
+    fn get_type(&self) -> String {
+        "reorder".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Reorder {
+            probability: self.probability,
+            delay_ms: self.delay.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram() -> Datagram {
+        Datagram {
+            data: vec![1, 2, 3, 4],
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn reorder_toxic_holds_back_with_probability() {
+        let toxic = ReorderToxic {
+            probability: 1.0,
+            delay: Duration::from_millis(50),
+        };
+        let datagrams = toxic.datagram(datagram(), &mut ToxicState::default());
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].delay, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn reorder_toxic_passes_with_zero_probability() {
+        let toxic = ReorderToxic {
+            probability: 0.0,
+            delay: Duration::from_millis(50),
+        };
+        assert_eq!(toxic.datagram(datagram(), &mut ToxicState::default()), vec![datagram()]);
+    }
+
+    #[test]
+    fn reorder_toxic_get_type_returns_correct_type() {
+        let toxic = ReorderToxic {
+            probability: 0.5,
+            delay: Duration::from_millis(50),
+        };
+        assert_eq!(toxic.get_type(), "reorder");
+    }
+
+    #[test]
+    fn reorder_toxic_get_kind_returns_correct_kind() {
+        let toxic = ReorderToxic {
+            probability: 0.5,
+            delay: Duration::from_millis(50),
+        };
+        if let ToxicKind::Reorder {
+            probability,
+            delay_ms,
+        } = toxic.get_kind()
+        {
+            assert_eq!(probability, 0.5);
+            assert_eq!(delay_ms, 50);
+        } else {
+            panic!("Expected ToxicKind::Reorder");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::toxic::{Link, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// Reset peer toxic drops the data it receives and, `timeout` after the connection opens, resets
+// it with a TCP RST instead of closing it gracefully. Idle connections are reset too.
+pub struct ResetPeerToxic {
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl Toxic for ResetPeerToxic {
+    async fn forward(
+        &self,
+        _data: Vec<u8>,
+        _state: &mut ToxicState,
+        _next: &mut dyn Link,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn until_close(&self, since: Instant) -> io::Error {
+        time::sleep_until((since + self.timeout).into()).await;
+        io::Error::new(io::ErrorKind::ConnectionReset, "reset by toxic")
+    }
+
+    fn get_type(&self) -> String {
+        "reset_peer".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::ResetPeer {
+            timeout_ms: self.timeout.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxic::RecordingLink;
+
+    #[tokio::test]
+    async fn reset_peer_toxic_drops_data() {
+        let toxic = ResetPeerToxic { timeout: Duration::from_millis(100) };
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut ToxicState::default(), &mut link).await.unwrap();
+        assert!(link.writes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reset_peer_toxic_resets_after_timeout() {
+        let toxic = ResetPeerToxic { timeout: Duration::from_millis(100) };
+        let start = Instant::now();
+        let error = toxic.until_close(start).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[tokio::test]
+    async fn reset_peer_toxic_with_zero_timeout_resets_immediately() {
+        let toxic = ResetPeerToxic { timeout: Duration::ZERO };
+        let reset = toxic.until_close(Instant::now());
+        let reset = time::timeout(Duration::from_millis(50), reset).await;
+        assert_eq!(reset.unwrap().kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn reset_peer_toxic_get_type_returns_correct_type() {
+        let toxic = ResetPeerToxic { timeout: Duration::from_millis(100) };
+        assert_eq!(toxic.get_type(), "reset_peer");
+    }
+
+    #[test]
+    fn reset_peer_toxic_get_kind_returns_correct_kind() {
+        let toxic = ResetPeerToxic { timeout: Duration::from_millis(100) };
+        if let ToxicKind::ResetPeer { timeout_ms } = toxic.get_kind() {
+            assert_eq!(timeout_ms, 100);
+        } else {
+            panic!("Expected ToxicKind::ResetPeer");
+        }
+    }
+}
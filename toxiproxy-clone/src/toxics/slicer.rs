@@ -0,0 +1,146 @@
+use crate::toxic::{Link, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::io;
+use std::time::Duration;
+use tokio::time;
+
+// Slicer toxic splits data into smaller writes of `average_size` bytes, give or take up to
+// `size_variation`, waiting `delay` between them. This exercises partial frame handling.
+pub struct SlicerToxic {
+    pub average_size: usize,
+    pub size_variation: usize,
+    pub delay: Duration,
+}
+
+impl SlicerToxic {
+    fn slice_sizes(&self, len: usize, rng: &mut StdRng) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut remaining = len;
+        while remaining > 0 {
+            let min = self.average_size.saturating_sub(self.size_variation).max(1);
+            let max = (self.average_size + self.size_variation).max(min);
+            let size = rng.random_range(min..=max).min(remaining);
+            sizes.push(size);
+            remaining -= size;
+        }
+        sizes
+    }
+
+    async fn slice(
+        &self,
+        mut data: Vec<u8>,
+        rng: &mut StdRng,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        if self.average_size == 0 {
+            return next.forward(data).await;
+        }
+
+        let sizes = self.slice_sizes(data.len(), rng);
+        for (i, size) in sizes.iter().enumerate() {
+            let rest = data.split_off(*size);
+            next.forward(data).await?;
+            data = rest;
+            if i + 1 < sizes.len() {
+                time::sleep(self.delay).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Toxic for SlicerToxic {
+    async fn forward(
+        &self,
+        data: Vec<u8>,
+        state: &mut ToxicState,
+        next: &mut dyn Link,
+    ) -> io::Result<()> {
+        self.slice(data, &mut state.rng, next).await
+    }
+
+    fn get_type(&self) -> String {
+        "slicer".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Slicer {
+            average_size: self.average_size,
+            size_variation: self.size_variation,
+            delay_us: self.delay.as_micros() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxic::RecordingLink;
+
+    fn slicer(average_size: usize, size_variation: usize, delay: Duration) -> SlicerToxic {
+        SlicerToxic { average_size, size_variation, delay }
+    }
+
+    #[tokio::test]
+    async fn slicer_toxic_splits_data_into_slices() {
+        let toxic = slicer(10, 0, Duration::ZERO);
+        let mut link = RecordingLink { writes: Vec::new() };
+        let data: Vec<u8> = (0..35).collect();
+        toxic.forward(data.clone(), &mut ToxicState::default(), &mut link).await.unwrap();
+        let sizes: Vec<usize> = link.writes.iter().map(|(_, slice)| slice.len()).collect();
+        assert_eq!(sizes, vec![10, 10, 10, 5]);
+        assert_eq!(link.data(), data);
+    }
+
+    #[tokio::test]
+    async fn slicer_toxic_keeps_slices_within_variation() {
+        let toxic = slicer(20, 5, Duration::ZERO);
+        let mut link = RecordingLink { writes: Vec::new() };
+        let data: Vec<u8> = (0..=255).collect();
+        toxic.forward(data.clone(), &mut ToxicState::default(), &mut link).await.unwrap();
+        let (last, slices) = link.writes.split_last().unwrap();
+        assert!(slices.iter().all(|(_, slice)| (15..=25).contains(&slice.len())));
+        assert!(last.1.len() <= 25);
+        assert_eq!(link.data(), data);
+    }
+
+    #[tokio::test]
+    async fn slicer_toxic_waits_between_slices() {
+        let toxic = slicer(1, 0, Duration::from_millis(20));
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut ToxicState::default(), &mut link).await.unwrap();
+        for pair in link.writes.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= Duration::from_millis(20));
+        }
+    }
+
+    #[tokio::test]
+    async fn slicer_toxic_with_zero_average_size_does_not_slice() {
+        let toxic = slicer(0, 0, Duration::ZERO);
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut ToxicState::default(), &mut link).await.unwrap();
+        assert_eq!(link.writes.len(), 1);
+    }
+
+    #[test]
+    fn slicer_toxic_get_type_returns_correct_type() {
+        let toxic = slicer(10, 5, Duration::from_micros(100));
+        assert_eq!(toxic.get_type(), "slicer");
+    }
+
+    #[test]
+    fn slicer_toxic_get_kind_returns_correct_kind() {
+        let toxic = slicer(10, 5, Duration::from_micros(100));
+        if let ToxicKind::Slicer { average_size, size_variation, delay_us } = toxic.get_kind() {
+            assert_eq!(average_size, 10);
+            assert_eq!(size_variation, 5);
+            assert_eq!(delay_us, 100);
+        } else {
+            panic!("Expected ToxicKind::Slicer");
+        }
+    }
+}
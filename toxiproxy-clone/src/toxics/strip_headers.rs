@@ -0,0 +1,97 @@
+use crate::http::{HttpRequest, HttpResponse};
+use crate::proxy::Protocol;
+use crate::toxic::{Toxic, ToxicKind};
+use async_trait::async_trait;
+
+// Strip headers toxic removes headers from requests upstream, or from responses downstream
+pub struct StripHeadersToxic {
+    pub headers: Vec<String>,
+    pub path_prefix: String,
+}
+
+#[async_trait]
+impl Toxic for StripHeadersToxic {
+    async fn request(&self, request: &mut HttpRequest) -> Option<HttpResponse> {
+        if request.matches(&self.path_prefix) {
+            for header in &self.headers {
+                request.remove_header(header);
+            }
+        }
+        None
+    }
+
+    async fn response(
+        &self,
+        request: &HttpRequest,
+        response: &mut HttpResponse,
+    ) -> Option<HttpResponse> {
+        if request.matches(&self.path_prefix) {
+            for header in &self.headers {
+                response.remove_header(header);
+            }
+        }
+        None
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Http
+    }
+
+    fn get_type(&self) -> String {
+        "strip_headers".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::StripHeaders {
+            headers: self.headers.clone(),
+            path_prefix: self.path_prefix.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toxic() -> StripHeadersToxic {
+        StripHeadersToxic {
+            headers: vec!["authorization".to_string()],
+            path_prefix: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn strip_headers_toxic_removes_request_headers() {
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            version: 1,
+            headers: vec![
+                ("Host".to_string(), "shop".to_string()),
+                ("Authorization".to_string(), "Bearer token".to_string()),
+            ],
+            body: Vec::new(),
+        };
+
+        assert_eq!(toxic().request(&mut request).await, None);
+
+        assert_eq!(
+            request.headers,
+            vec![("Host".to_string(), "shop".to_string())]
+        );
+    }
+
+    #[test]
+    fn strip_headers_toxic_get_type_returns_correct_type() {
+        assert_eq!(toxic().get_type(), "strip_headers");
+    }
+
+    #[test]
+    fn strip_headers_toxic_get_kind_returns_correct_kind() {
+        if let ToxicKind::StripHeaders { headers, .. } = toxic().get_kind() {
+            assert_eq!(headers, vec!["authorization"]);
+        } else {
+            panic!("Expected ToxicKind::StripHeaders");
+        }
+    }
+}
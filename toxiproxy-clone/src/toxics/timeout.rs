@@ -0,0 +1,89 @@
+use crate::toxic::{Link, Toxic, ToxicKind, ToxicState};
+use async_trait::async_trait;
+use std::future;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// Timeout toxic stops all data from getting through and closes the connection after
+// `timeout`. A timeout of zero holds the connection open until the toxic is removed.
+pub struct TimeoutToxic {
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl Toxic for TimeoutToxic {
+    async fn forward(
+        &self,
+        _data: Vec<u8>,
+        _state: &mut ToxicState,
+        _next: &mut dyn Link,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn until_close(&self, since: Instant) -> io::Error {
+        if self.timeout.is_zero() {
+            return future::pending().await;
+        }
+        time::sleep_until((since + self.timeout).into()).await;
+        io::Error::new(io::ErrorKind::ConnectionAborted, "closed by toxic")
+    }
+
+    fn get_type(&self) -> String {
+        "timeout".to_string()
+    }
+
+    fn get_kind(&self) -> ToxicKind {
+        ToxicKind::Timeout {
+            timeout_ms: self.timeout.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toxic::RecordingLink;
+
+    #[tokio::test]
+    async fn timeout_toxic_drops_data() {
+        let toxic = TimeoutToxic { timeout: Duration::from_millis(100) };
+        let mut link = RecordingLink { writes: Vec::new() };
+        toxic.forward(vec![1, 2, 3, 4], &mut ToxicState::default(), &mut link).await.unwrap();
+        assert!(link.writes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn timeout_toxic_closes_after_timeout() {
+        let toxic = TimeoutToxic { timeout: Duration::from_millis(100) };
+        let start = Instant::now();
+        let error = toxic.until_close(start).await;
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionAborted);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn timeout_toxic_with_zero_timeout_never_closes() {
+        let toxic = TimeoutToxic { timeout: Duration::ZERO };
+        let closed = toxic.until_close(Instant::now());
+        let closed = time::timeout(Duration::from_millis(100), closed).await;
+        assert!(closed.is_err());
+    }
+
+    #[test]
+    fn timeout_toxic_get_type_returns_correct_type() {
+        let toxic = TimeoutToxic { timeout: Duration::from_millis(100) };
+        assert_eq!(toxic.get_type(), "timeout");
+    }
+
+    #[test]
+    fn timeout_toxic_get_kind_returns_correct_kind() {
+        let toxic = TimeoutToxic { timeout: Duration::from_millis(100) };
+        if let ToxicKind::Timeout { timeout_ms } = toxic.get_kind() {
+            assert_eq!(timeout_ms, 100);
+        } else {
+            panic!("Expected ToxicKind::Timeout");
+        }
+    }
+}
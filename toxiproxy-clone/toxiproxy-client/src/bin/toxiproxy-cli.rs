@@ -0,0 +1,492 @@
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+use toxiproxy_client::{
+    Client, ConnectionInfo, ProxyConfig, ProxyResponse, Scenario, ScenarioStatus, ToxicConfig,
+    ToxicKind, ToxicResponse, UpdateProxyRequest,
+};
+
+// CLI Arguments
+#[derive(Parser, Debug)]
+#[command(
+    name = "toxiproxy-cli",
+    about = "Manage proxies and toxics through the Toxiproxy REST API",
+    version
+)]
+struct Cli {
+    /// Base URL of the REST API
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8474")]
+    url: String,
+
+    /// Print the API responses as JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List all proxies
+    List,
+    /// Create a proxy and start its listener
+    Create {
+        name: String,
+        /// Address to listen on, port 0 picks a free port
+        #[arg(long)]
+        listen: String,
+        /// Address of the upstream service
+        #[arg(long)]
+        upstream: String,
+        /// Protocol to proxy: tcp, udp or http
+        #[arg(long, default_value = "tcp")]
+        protocol: String,
+        /// File on the server to append a JSON-lines transcript of the traffic to
+        #[arg(long)]
+        capture: Option<PathBuf>,
+    },
+    /// Delete a proxy and close its listener
+    Delete { name: String },
+    /// Disable an enabled proxy, or enable a disabled one
+    Toggle { name: String },
+    /// Show a proxy with its toxics
+    Inspect { name: String },
+    /// Manage the toxics of a proxy
+    #[command(subcommand)]
+    Toxic(ToxicCommand),
+    /// Inspect and drop the open connections of a proxy
+    #[command(subcommand)]
+    Connection(ConnectionCommand),
+    /// Run timelines of toxic changes on the server
+    #[command(subcommand)]
+    Scenario(ScenarioCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum ScenarioCommand {
+    /// List the running scenarios
+    List,
+    /// Start the scenario in a JSON or TOML file
+    Start { file: PathBuf },
+    /// Stop a scenario before its remaining steps, leaving its toxics in place
+    Stop { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConnectionCommand {
+    /// List the open connections of a proxy
+    List { proxy: String },
+    /// Drop an open connection, closing both of its sides
+    Close { proxy: String, id: u64 },
+}
+
+#[derive(Subcommand, Debug)]
+enum ToxicCommand {
+    /// Add a toxic to a proxy
+    Add {
+        proxy: String,
+        /// Toxic type, e.g. latency or slow_close
+        #[arg(short = 't', long = "type")]
+        toxic_type: String,
+        /// Toxic name, defaults to the type and stream
+        #[arg(short, long)]
+        name: Option<String>,
+        #[command(flatten)]
+        options: ToxicOptions,
+    },
+    /// Change some of the settings of a toxic, keeping the rest
+    Update {
+        proxy: String,
+        name: String,
+        #[command(flatten)]
+        options: ToxicOptions,
+    },
+    /// Remove a toxic from a proxy
+    Remove { proxy: String, name: String },
+}
+
+#[derive(Args, Debug)]
+struct ToxicOptions {
+    /// Direction the toxic applies to: upstream or downstream
+    #[arg(short, long)]
+    stream: Option<String>,
+    /// Share of connections the toxic applies to, from 0.0 to 1.0
+    #[arg(long)]
+    toxicity: Option<f64>,
+    /// Toxic attribute, e.g. -a latency_ms=100. Can be given more than once.
+    #[arg(short, long = "attribute", value_name = "KEY=VALUE")]
+    attributes: Vec<String>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let client = Client::new(&cli.url);
+
+    match cli.command {
+        Command::List => {
+            let proxies = client.proxies()?;
+            if cli.json {
+                return print_json(&proxies);
+            }
+            let mut proxies: Vec<_> = proxies.into_values().collect();
+            proxies.sort_by(|a, b| a.config.name.cmp(&b.config.name));
+            print!("{}", proxy_table(&proxies));
+        }
+        Command::Create {
+            name,
+            listen,
+            upstream,
+            protocol,
+            capture,
+        } => {
+            let protocol = serde_json::from_value(Value::String(protocol))?;
+            let proxy = client.create_proxy(&ProxyConfig {
+                name,
+                listen,
+                upstream,
+                protocol,
+                enabled: true,
+                tls: None,
+                capture,
+            })?;
+            if cli.json {
+                return print_json(&proxy);
+            }
+            println!(
+                "Created proxy {} listening on {}",
+                proxy.config.name, proxy.config.listen
+            );
+        }
+        Command::Delete { name } => {
+            client.proxy(&name).delete()?;
+            if !cli.json {
+                println!("Deleted proxy {}", name);
+            }
+        }
+        Command::Toggle { name } => {
+            let proxy = client.proxy(&name);
+            let enabled = !proxy.get()?.config.enabled;
+            let updated = proxy.update(&UpdateProxyRequest {
+                enabled: Some(enabled),
+                ..Default::default()
+            })?;
+            if cli.json {
+                return print_json(&updated);
+            }
+            let state = if enabled { "enabled" } else { "disabled" };
+            println!("Proxy {} is now {}", name, state);
+        }
+        Command::Inspect { name } => {
+            let proxy = client.proxy(&name).get()?;
+            if cli.json {
+                return print_json(&proxy);
+            }
+            println!("Name:     {}", proxy.config.name);
+            println!("Listen:   {}", proxy.config.listen);
+            println!("Upstream: {}", proxy.config.upstream);
+            println!("Enabled:  {}", proxy.config.enabled);
+            println!();
+            if proxy.toxics.is_empty() {
+                println!("No toxics");
+            } else {
+                print!("{}", toxic_table(&proxy.toxics));
+            }
+        }
+        Command::Toxic(ToxicCommand::Add {
+            proxy,
+            toxic_type,
+            name,
+            options,
+        }) => {
+            let mut config = Map::new();
+            config.insert("type".to_string(), Value::String(kind_name(&toxic_type)));
+            if let Some(name) = name {
+                config.insert("name".to_string(), Value::String(name));
+            }
+            let config = toxic_config(config, &options)?;
+            let toxic = client.proxy(&proxy).add_toxic(config)?.keep();
+            if cli.json {
+                return print_json(&toxic);
+            }
+            println!("Added toxic {} to proxy {}", toxic.config.name, proxy);
+        }
+        Command::Toxic(ToxicCommand::Update {
+            proxy,
+            name,
+            options,
+        }) => {
+            let proxy = client.proxy(&proxy);
+            // The API replaces the whole toxic, so start from its current settings
+            let Value::Object(current) = serde_json::to_value(proxy.toxic(&name)?.config)? else {
+                unreachable!("toxic configurations serialize to objects");
+            };
+            let toxic = proxy.update_toxic(&name, toxic_config(current, &options)?)?;
+            if cli.json {
+                return print_json(&toxic);
+            }
+            println!("Updated toxic {} on proxy {}", name, proxy.name());
+        }
+        Command::Toxic(ToxicCommand::Remove { proxy, name }) => {
+            client.proxy(&proxy).remove_toxic(&name)?;
+            if !cli.json {
+                println!("Removed toxic {} from proxy {}", name, proxy);
+            }
+        }
+        Command::Connection(ConnectionCommand::List { proxy }) => {
+            let connections = client.proxy(&proxy).connections()?;
+            if cli.json {
+                return print_json(&connections);
+            }
+            print!("{}", connection_table(&connections));
+        }
+        Command::Connection(ConnectionCommand::Close { proxy, id }) => {
+            client.proxy(&proxy).close_connection(id)?;
+            if !cli.json {
+                println!("Closed connection {} on proxy {}", id, proxy);
+            }
+        }
+        Command::Scenario(ScenarioCommand::List) => {
+            let scenarios = client.scenarios()?;
+            if cli.json {
+                return print_json(&scenarios);
+            }
+            print!("{}", scenario_table(&scenarios));
+        }
+        Command::Scenario(ScenarioCommand::Start { file }) => {
+            let status = client.start_scenario(&Scenario::load(&file)?)?;
+            if cli.json {
+                return print_json(&status);
+            }
+            println!(
+                "Started scenario {} with {} steps",
+                status.scenario.name,
+                status.scenario.steps.len()
+            );
+        }
+        Command::Scenario(ScenarioCommand::Stop { name }) => {
+            client.stop_scenario(&name)?;
+            if !cli.json {
+                println!("Stopped scenario {}", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// Turns a type given on the command line, such as `slow_close`, into its `ToxicKind` tag
+fn kind_name(toxic_type: &str) -> String {
+    toxic_type
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+// Applies the command line options on top of a toxic configuration in its JSON form
+fn toxic_config(
+    mut config: Map<String, Value>,
+    options: &ToxicOptions,
+) -> Result<ToxicConfig, Box<dyn Error>> {
+    if let Some(stream) = &options.stream {
+        config.insert("stream".to_string(), Value::String(stream.clone()));
+    }
+    if let Some(toxicity) = options.toxicity {
+        config.insert("toxicity".to_string(), toxicity.into());
+    }
+    for attribute in &options.attributes {
+        let (key, value) = attribute
+            .split_once('=')
+            .ok_or_else(|| format!("attribute must be KEY=VALUE: {}", attribute))?;
+        // Numbers and booleans are passed as such, anything else as a string
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        config.insert(key.to_string(), value);
+    }
+    Ok(serde_json::from_value(Value::Object(config))?)
+}
+
+// The settings of a toxic other than its type, e.g. `latency_ms=100`
+fn attributes(kind: &ToxicKind) -> String {
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(kind) else {
+        return String::new();
+    };
+    fields.remove("type");
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn proxy_table(proxies: &[ProxyResponse]) -> String {
+    let rows = proxies
+        .iter()
+        .map(|proxy| {
+            vec![
+                proxy.config.name.clone(),
+                proxy.config.listen.clone(),
+                proxy.config.upstream.clone(),
+                proxy.config.enabled.to_string(),
+                proxy.toxics.len().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table(&["NAME", "LISTEN", "UPSTREAM", "ENABLED", "TOXICS"], &rows)
+}
+
+fn toxic_table(toxics: &[ToxicResponse]) -> String {
+    let rows = toxics
+        .iter()
+        .map(|toxic| {
+            vec![
+                toxic.config.name.clone(),
+                toxic.toxic_type.clone(),
+                toxic.config.stream.to_string(),
+                toxic.config.toxicity.to_string(),
+                attributes(&toxic.config.kind),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table(&["NAME", "TYPE", "STREAM", "TOXICITY", "ATTRIBUTES"], &rows)
+}
+
+fn connection_table(connections: &[ConnectionInfo]) -> String {
+    let rows = connections
+        .iter()
+        .map(|connection| {
+            vec![
+                connection.id.to_string(),
+                connection.client.to_string(),
+                connection.bytes_upstream.to_string(),
+                connection.bytes_downstream.to_string(),
+                connection.toxics.join(","),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table(&["ID", "CLIENT", "UPSTREAM", "DOWNSTREAM", "TOXICS"], &rows)
+}
+
+fn scenario_table(scenarios: &[ScenarioStatus]) -> String {
+    let rows = scenarios
+        .iter()
+        .map(|status| {
+            vec![
+                status.scenario.name.clone(),
+                format!("{}/{}", status.completed, status.scenario.steps.len()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table(&["NAME", "STEPS"], &rows)
+}
+
+// Left-aligned columns, each as wide as its widest cell
+fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    let mut output = String::new();
+    for row in std::iter::once(&headers).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toxiproxy_client::Stream;
+
+    fn options(attributes: &[&str]) -> ToxicOptions {
+        ToxicOptions {
+            stream: None,
+            toxicity: None,
+            attributes: attributes.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn kind_name_converts_snake_case_types() {
+        assert_eq!(kind_name("latency"), "Latency");
+        assert_eq!(kind_name("slow_close"), "SlowClose");
+        assert_eq!(kind_name("SlowClose"), "SlowClose");
+    }
+
+    #[test]
+    fn toxic_config_reads_options_and_attributes() {
+        let mut config = Map::new();
+        config.insert("type".to_string(), Value::String("Latency".to_string()));
+        let options = ToxicOptions {
+            stream: Some("upstream".to_string()),
+            toxicity: Some(0.5),
+            ..options(&["latency_ms=250"])
+        };
+
+        let config = toxic_config(config, &options).unwrap();
+
+        assert_eq!(config.stream, Stream::Upstream);
+        assert_eq!(config.toxicity, 0.5);
+        assert!(matches!(config.kind, ToxicKind::Latency { latency_ms: 250, .. }));
+    }
+
+    #[test]
+    fn toxic_config_rejects_malformed_attribute() {
+        let mut config = Map::new();
+        config.insert("type".to_string(), Value::String("Latency".to_string()));
+
+        assert!(toxic_config(config, &options(&["latency_ms"])).is_err());
+    }
+
+    #[test]
+    fn attributes_lists_settings_without_type() {
+        let kind = ToxicKind::Slicer {
+            average_size: 16,
+            size_variation: 8,
+            delay_us: 500,
+        };
+        assert_eq!(
+            attributes(&kind),
+            "average_size=16 delay_us=500 size_variation=8"
+        );
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let rows = vec![vec!["postgres".to_string(), "1".to_string()]];
+        assert_eq!(
+            table(&["NAME", "TOXICS"], &rows),
+            "NAME      TOXICS\npostgres  1\n"
+        );
+    }
+}
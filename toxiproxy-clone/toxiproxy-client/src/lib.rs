@@ -0,0 +1,403 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use ureq::http::Response;
+use ureq::{Agent, Body};
+
+pub use toxiproxy_clone::config::{Config, ProxyDefinition};
+pub use toxiproxy_clone::events::{EventRecord, ProxyEvent};
+pub use toxiproxy_clone::proxy::{ConnectionInfo, Protocol, ProxyConfig};
+pub use toxiproxy_clone::rest_api::{ProxyResponse, ToxicResponse, UpdateProxyRequest};
+pub use toxiproxy_clone::scenario::{Action, Scenario, ScenarioStatus, Step};
+pub use toxiproxy_clone::tls::TlsConfig;
+pub use toxiproxy_clone::toxic::{
+    Distribution, Jitter, LatencyScope, Stream, ToxicConfig, ToxicKind,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    // The request didn't get a response from the API
+    Http(ureq::Error),
+    // The API answered with an error status
+    Api { status: u16, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Api { status, message } => write!(f, "{} ({})", message, status),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ureq::Error> for Error {
+    fn from(e: ureq::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Blocking client for the REST API. Requests are synchronous so toxics can be removed from
+// `Drop`; from async code, run the server in another process or runtime than the client.
+#[derive(Clone)]
+pub struct Client {
+    agent: Agent,
+    url: String,
+}
+
+impl Client {
+    pub fn new(url: &str) -> Self {
+        // Error statuses are read from the response so their message can be reported
+        let agent = Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Client {
+            agent,
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn proxies(&self) -> Result<HashMap<String, ProxyResponse>> {
+        self.get("/proxies")
+    }
+
+    pub fn create_proxy(&self, config: &ProxyConfig) -> Result<ProxyResponse> {
+        self.post("/proxies", config)
+    }
+
+    // Replaces every proxy on the server with the ones in `config`
+    pub fn populate(&self, config: &Config) -> Result<HashMap<String, ProxyResponse>> {
+        self.post("/populate", config)
+    }
+
+    // Removes every toxic and enables every proxy again
+    pub fn reset(&self) -> Result<()> {
+        let response = self
+            .agent
+            .post(format!("{}/reset", self.url))
+            .send_empty()?;
+        Self::check_status(response).map(|_| ())
+    }
+
+    // Handle for a proxy. No request is made until one of its methods is called.
+    pub fn proxy(&self, name: &str) -> ProxyClient<'_> {
+        ProxyClient {
+            client: self,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn toxics(&self) -> Result<Vec<ToxicResponse>> {
+        self.get("/toxics")
+    }
+
+    pub fn scenarios(&self) -> Result<Vec<ScenarioStatus>> {
+        self.get("/scenarios")
+    }
+
+    // Starts running a timeline of toxic changes on the server
+    pub fn start_scenario(&self, scenario: &Scenario) -> Result<ScenarioStatus> {
+        self.post("/scenarios", scenario)
+    }
+
+    // Stops a scenario before its remaining steps, leaving its toxics in place
+    pub fn stop_scenario(&self, name: &str) -> Result<()> {
+        self.delete(&format!("/scenarios/{}", name))
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let response = self.agent.get(format!("{}{}", self.url, path)).call()?;
+        Self::read_json(response)
+    }
+
+    fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let response = self
+            .agent
+            .post(format!("{}{}", self.url, path))
+            .send_json(body)?;
+        Self::read_json(response)
+    }
+
+    fn delete(&self, path: &str) -> Result<()> {
+        let response = self.agent.delete(format!("{}{}", self.url, path)).call()?;
+        Self::check_status(response).map(|_| ())
+    }
+
+    fn read_json<T: DeserializeOwned>(response: Response<Body>) -> Result<T> {
+        Ok(Self::check_status(response)?.body_mut().read_json()?)
+    }
+
+    fn check_status(mut response: Response<Body>) -> Result<Response<Body>> {
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(Error::Api {
+                status: status.as_u16(),
+                message: response.body_mut().read_to_string()?,
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Clone)]
+pub struct ProxyClient<'a> {
+    client: &'a Client,
+    name: String,
+}
+
+impl<'a> ProxyClient<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self) -> Result<ProxyResponse> {
+        self.client.get(&self.path(""))
+    }
+
+    pub fn update(&self, request: &UpdateProxyRequest) -> Result<ProxyResponse> {
+        self.client.post(&self.path(""), request)
+    }
+
+    pub fn delete(&self) -> Result<()> {
+        self.client.delete(&self.path(""))
+    }
+
+    pub fn toxics(&self) -> Result<Vec<ToxicResponse>> {
+        self.client.get(&self.path("/toxics"))
+    }
+
+    pub fn toxic(&self, name: &str) -> Result<ToxicResponse> {
+        self.client.get(&self.path(&format!("/toxics/{}", name)))
+    }
+
+    // Adds a toxic that stays in place until the returned guard is dropped
+    pub fn add_toxic(&self, config: impl Into<ToxicConfig>) -> Result<ToxicGuard<'a>> {
+        let toxic = self.client.post(&self.path("/toxics"), &config.into())?;
+        Ok(ToxicGuard {
+            proxy: self.clone(),
+            toxic: Some(toxic),
+        })
+    }
+
+    pub fn update_toxic(&self, name: &str, config: impl Into<ToxicConfig>) -> Result<ToxicResponse> {
+        self.client
+            .post(&self.path(&format!("/toxics/{}", name)), &config.into())
+    }
+
+    pub fn remove_toxic(&self, name: &str) -> Result<()> {
+        self.client.delete(&self.path(&format!("/toxics/{}", name)))
+    }
+
+    pub fn connections(&self) -> Result<Vec<ConnectionInfo>> {
+        self.client.get(&self.path("/connections"))
+    }
+
+    // Drops one open connection, closing both of its sides
+    pub fn close_connection(&self, id: u64) -> Result<()> {
+        self.client.delete(&self.path(&format!("/connections/{}", id)))
+    }
+
+    fn path(&self, rest: &str) -> String {
+        format!("/proxies/{}{}", self.name, rest)
+    }
+}
+
+// Removes its toxic from the proxy when dropped, so a failing test doesn't leave faults behind
+pub struct ToxicGuard<'a> {
+    proxy: ProxyClient<'a>,
+    toxic: Option<ToxicResponse>,
+}
+
+impl ToxicGuard<'_> {
+    pub fn toxic(&self) -> &ToxicResponse {
+        self.toxic.as_ref().expect("toxic is only taken on drop or keep")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.toxic().config.name
+    }
+
+    // Leaves the toxic in place after the guard is gone
+    pub fn keep(mut self) -> ToxicResponse {
+        self.toxic.take().expect("toxic is only taken on drop or keep")
+    }
+}
+
+impl Drop for ToxicGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(toxic) = self.toxic.take() {
+            if let Err(e) = self.proxy.remove_toxic(&toxic.config.name) {
+                eprintln!("Failed to remove toxic {}: {}", toxic.config.name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use toxiproxy_clone::proxy::ProxyState;
+    use toxiproxy_clone::rest_api;
+    use toxiproxy_clone::scenario::Scenarios;
+
+    // Runs the REST API on its own runtime, as a separate server process would
+    fn start_server() -> Client {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                sender.send(listener.local_addr().unwrap()).unwrap();
+                let state: ProxyState = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+                let router = rest_api::router(state, Scenarios::default());
+                axum::serve(listener, router).await.unwrap();
+            });
+        });
+        Client::new(&format!("http://{}", receiver.recv().unwrap()))
+    }
+
+    fn create_proxy(client: &Client, name: &str) -> ProxyResponse {
+        client
+            .create_proxy(&ProxyConfig {
+                name: name.to_string(),
+                listen: "127.0.0.1:0".to_string(),
+                upstream: "127.0.0.1:8476".to_string(),
+                protocol: Protocol::Tcp,
+                enabled: true,
+                tls: None,
+                capture: None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn client_creates_and_lists_proxies() {
+        let client = start_server();
+        let created = create_proxy(&client, "pg");
+
+        let proxies = client.proxies().unwrap();
+
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies["pg"].config.listen, created.config.listen);
+    }
+
+    #[test]
+    fn client_reports_api_errors() {
+        let client = start_server();
+
+        let error = client.proxy("missing").get().unwrap_err();
+
+        assert!(matches!(error, Error::Api { status: 404, .. }));
+        assert_eq!(error.to_string(), "proxy not found: missing (404)");
+    }
+
+    #[test]
+    fn toxic_guard_removes_toxic_on_drop() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        let proxy = client.proxy("pg");
+
+        {
+            let guard = proxy
+                .add_toxic(ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE })
+                .unwrap();
+            assert_eq!(guard.name(), "latency_downstream");
+            assert_eq!(proxy.toxics().unwrap().len(), 1);
+        }
+
+        assert!(proxy.toxics().unwrap().is_empty());
+    }
+
+    #[test]
+    fn toxic_guard_keep_leaves_toxic_in_place() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        let proxy = client.proxy("pg");
+
+        let toxic = proxy
+            .add_toxic(ToxicKind::Timeout { timeout_ms: 0 })
+            .unwrap()
+            .keep();
+
+        assert_eq!(proxy.toxic(&toxic.config.name).unwrap().toxic_type, "timeout");
+    }
+
+    #[test]
+    fn client_reset_removes_toxics() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        client
+            .proxy("pg")
+            .add_toxic(ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE })
+            .unwrap()
+            .keep();
+
+        client.reset().unwrap();
+
+        assert!(client.toxics().unwrap().is_empty());
+    }
+
+    #[test]
+    fn proxy_client_reports_unknown_connection() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        let proxy = client.proxy("pg");
+
+        assert!(proxy.connections().unwrap().is_empty());
+        let error = proxy.close_connection(7).unwrap_err();
+        assert_eq!(error.to_string(), "connection not found: pg/7 (404)");
+    }
+
+    #[test]
+    fn client_starts_and_stops_scenarios() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        let scenario = Scenario {
+            name: "outage".to_string(),
+            steps: vec![Step {
+                at_ms: 60_000,
+                proxy: "pg".to_string(),
+                action: Action::Reset,
+            }],
+        };
+
+        assert_eq!(client.start_scenario(&scenario).unwrap().completed, 0);
+        assert_eq!(client.scenarios().unwrap().len(), 1);
+        client.stop_scenario("outage").unwrap();
+        assert!(client.scenarios().unwrap().is_empty());
+    }
+
+    #[test]
+    fn proxy_client_updates_toxic() {
+        let client = start_server();
+        create_proxy(&client, "pg");
+        let proxy = client.proxy("pg");
+        let guard = proxy
+            .add_toxic(ToxicConfig {
+                name: "lag".to_string(),
+                stream: Stream::Upstream,
+                ..ToxicKind::Latency { latency_ms: 100, jitter: Jitter::NONE }.into()
+            })
+            .unwrap();
+
+        let latency = ToxicKind::Latency {
+            latency_ms: 500,
+            jitter: Jitter::NONE,
+        };
+        let updated = proxy.update_toxic(guard.name(), latency).unwrap();
+
+        assert!(matches!(
+            updated.config.kind,
+            ToxicKind::Latency { latency_ms: 500, .. }
+        ));
+        assert_eq!(updated.config.stream, Stream::Downstream);
+    }
+}